@@ -0,0 +1,39 @@
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+use std::fs::File;
+use std::io::BufWriter;
+
+// streams snapshots of the generation process into an animated gif as they're taken,
+// instead of writing every snapshot out as its own png
+pub struct Recorder
+{
+    encoder : GifEncoder<BufWriter<File>>,
+    delay_ms : u32,
+    frame_count : usize,
+}
+
+impl Recorder
+{
+    pub fn new(path : &str, delay_ms : u32) -> Recorder
+    {
+        let file = BufWriter::new(File::create(path).unwrap());
+        // speed 10 is the gif crate's default; speed 1 is unbearably slow on big maps
+        let mut encoder = GifEncoder::new_with_speed(file, 10);
+        encoder.set_repeat(Repeat::Infinite).unwrap();
+        Recorder { encoder, delay_ms, frame_count : 0 }
+    }
+    pub fn push(&mut self, image : RgbaImage)
+    {
+        self.push_with_delay(image, self.delay_ms);
+    }
+    pub fn push_with_delay(&mut self, image : RgbaImage, delay_ms : u32)
+    {
+        let frame = Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1));
+        self.encoder.encode_frame(frame).unwrap();
+        self.frame_count += 1;
+    }
+    pub fn frame_count(&self) -> usize
+    {
+        self.frame_count
+    }
+}
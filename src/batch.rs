@@ -0,0 +1,73 @@
+use crate::Collapser;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+// how one map of a batch turned out
+pub struct Outcome
+{
+    pub seed : u128,
+    pub path : String,
+    pub dead_cells : usize,
+    pub counts_ok : bool,
+    pub connected : bool,
+}
+
+// the seeds for a batch of maps. the first one is the base seed itself and the rest come from an rng seeded with it,
+// so every map's seed only depends on the base seed and its place in the batch, not on how big the batch is
+pub fn derive_seeds(base : u128, count : usize) -> Vec<u128>
+{
+    let mut rng = oorandom::Rand64::new(base);
+    std::iter::once(base).chain((1..count).map(|_| rng.rand_u64() as u128)).take(count).collect()
+}
+
+// makes a map per seed, each from a fork of the template (which is set up but not run yet), on up to that many threads
+// at once. every map is written out as "{stem}_{seed}" plus the usual extensions, so any of them can be made again on
+// its own by running with its seed
+pub fn run_batch(template : &Collapser, stem : &str, seeds : &[u128], threads : usize, write_tiled : bool, write_grid : bool) -> Vec<Outcome>
+{
+    let next = AtomicUsize::new(0);
+    let outcomes = Mutex::new(Vec::new());
+    std::thread::scope(|scope|
+    {
+        for _ in 0..std::cmp::min(threads, seeds.len())
+        {
+            scope.spawn(||
+            {
+                loop
+                {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    if i >= seeds.len()
+                    {
+                        break;
+                    }
+                    let seed = seeds[i];
+                    let run_stem = format!("{}_{}", stem, seed);
+                    let path = format!("{}.png", run_stem);
+                    let mut collapser = template.fork(path.clone());
+                    collapser.run(seed);
+                    let dead_cells = collapser.dead_cells().len();
+                    let counts_ok = collapser.check_count_constraints();
+                    let connected = collapser.check_connectivity();
+                    collapser.write_outputs(&run_stem, write_tiled, write_grid);
+                    outcomes.lock().unwrap().push((i, Outcome { seed, path, dead_cells, counts_ok, connected }));
+                }
+            });
+        }
+    });
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by_key(|(i, _)| *i);
+    outcomes.into_iter().map(|(_, outcome)| outcome).collect()
+}
+
+// one line per map, in seed order, for picking the best ones afterwards
+pub fn write_summary(path : &str, outcomes : &[Outcome])
+{
+    let mut out = String::new();
+    writeln!(out, "seed,file,dead_cells,counts_ok,connected").unwrap();
+    for outcome in outcomes
+    {
+        writeln!(out, "{},{},{},{},{}", outcome.seed, crate::export::file_name(&outcome.path), outcome.dead_cells, outcome.counts_ok, outcome.connected).unwrap();
+    }
+    std::fs::write(path, out).unwrap();
+}
@@ -0,0 +1,453 @@
+use crate::{get_opposite_direction, rng, Collapser, TileId, TileType, DIRECTIONS};
+
+// the bitset solver's state: which tiles each cell can still be, one bit per tile. arc consistency is kept the ac-4
+// way: every (cell, tile, direction) counts how many tiles left in that neighbor allow it, and removing a tile takes
+// its support away from whatever it allowed, so propagating a removal costs the tiles it touches instead of a rescan
+// of everything left in the cell. the fields are still kept, but only for sampling: they get worked out once for the
+// cells around what changed instead of following damage around the map
+#[derive(Clone)]
+pub struct Possibilities
+{
+    width : usize,
+    height : usize,
+    max_index : usize,
+    // u64s per cell
+    words : usize,
+    // row-major like out_map_types; undecided cells start with every tile except 0, pinned cells with just theirs
+    domains : Vec<u64>,
+    // compat[(a*DIRECTIONS.len() + dir)*words..] = the tiles that may sit in direction dir of tile a
+    compat : Vec<u64>,
+    // backing[(b*DIRECTIONS.len() + dir)*words..] = the tiles b allows in direction dir of itself, i.e. the ones it
+    // counts towards the support of
+    backing : Vec<u64>,
+    // support[(cell*max_index + a)*DIRECTIONS.len() + dir] = how many tiles left in the neighbor in direction dir
+    // allow a; dead neighbors don't constrain anything, so they count as u16::MAX
+    support : Vec<u16>,
+    // (cell, tile) removals that haven't taken their support away from the neighbors yet
+    pending : Vec<(usize, TileId)>,
+    // (word index, old value) of every change since the trail was last cleared, so a failed decision can be undone
+    trail : Vec<(usize, u64)>,
+    // every support count taken down since then, to put back along with it
+    support_trail : Vec<usize>,
+    // cells that were marked dead since the last time someone took them
+    pub killed : Vec<usize>,
+}
+
+impl Possibilities
+{
+    pub fn new(ships : &[f64], max_index : usize, types : &[TileType], width : usize, height : usize) -> Possibilities
+    {
+        assert!(max_index <= u16::MAX as usize, "the bitset solver handles at most {} tiles", u16::MAX);
+        let words = max_index.div_ceil(64);
+        let mut compat = vec!(0; max_index*DIRECTIONS.len()*words);
+        let mut backing = vec!(0; max_index*DIRECTIONS.len()*words);
+        for a in 0..max_index
+        {
+            for b in 0..max_index
+            {
+                for dir in 0..DIRECTIONS.len()
+                {
+                    if ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + dir] != 0.0
+                    {
+                        compat[(a*DIRECTIONS.len() + dir)*words + b/64] |= 1 << (b%64);
+                        backing[(b*DIRECTIONS.len() + get_opposite_direction(dir))*words + a/64] |= 1 << (a%64);
+                    }
+                }
+            }
+        }
+        let mut domains = vec!(0; width*height*words);
+        for (cell, tile_type) in types.iter().enumerate()
+        {
+            let domain = &mut domains[cell*words..(cell+1)*words];
+            match tile_type
+            {
+                TileType::Tile(id) => domain[id/64] |= 1 << (id%64),
+                TileType::Field =>
+                {
+                    for id in 1..max_index
+                    {
+                        domain[id/64] |= 1 << (id%64);
+                    }
+                }
+                TileType::Dead => {}
+            }
+        }
+        let support = vec!(0; width*height*max_index*DIRECTIONS.len());
+        let mut state = Possibilities { width, height, max_index, words, domains, compat, backing, support, pending : Vec::new(), trail : Vec::new(), support_trail : Vec::new(), killed : Vec::new() };
+        state.count_support(types);
+        state
+    }
+    // sets every support count from the domains as they are
+    fn count_support(&mut self, types : &[TileType])
+    {
+        let words = self.words;
+        for cell in 0..self.width*self.height
+        {
+            for dir in 0..DIRECTIONS.len()
+            {
+                let neighbor = self.neighbor(cell, dir);
+                for a in 0..self.max_index
+                {
+                    let start = (a*DIRECTIONS.len() + dir)*words;
+                    let count = match types[neighbor]
+                    {
+                        TileType::Dead => u16::MAX,
+                        _ => (0..words).map(|w| (self.domains[neighbor*words + w] & self.compat[start + w]).count_ones() as u16).sum(),
+                    };
+                    self.support[(cell*self.max_index + a)*DIRECTIONS.len() + dir] = count;
+                }
+            }
+        }
+    }
+    pub fn cell(&self, x : isize, y : isize) -> usize
+    {
+        let x = x.wrapping_rem_euclid(self.width as isize) as usize;
+        let y = y.wrapping_rem_euclid(self.height as isize) as usize;
+        y*self.width + x
+    }
+    fn neighbor(&self, cell : usize, dir : usize) -> usize
+    {
+        let (x, y) = ((cell%self.width) as isize, (cell/self.width) as isize);
+        self.cell(x + DIRECTIONS[dir].0, y + DIRECTIONS[dir].1)
+    }
+    pub fn tiles(&self, cell : usize) -> Vec<TileId>
+    {
+        let mut tiles = Vec::new();
+        for w in 0..self.words
+        {
+            let mut bits = self.domains[cell*self.words + w];
+            while bits != 0
+            {
+                tiles.push(w*64 + bits.trailing_zeros() as usize);
+                bits &= bits - 1;
+            }
+        }
+        tiles
+    }
+    pub fn has(&self, cell : usize, id : TileId) -> bool
+    {
+        self.domains[cell*self.words + id/64] & 1 << (id%64) != 0
+    }
+    pub fn words(&self) -> usize
+    {
+        self.words
+    }
+    pub fn count(&self, cell : usize) -> usize
+    {
+        self.domains[cell*self.words..(cell+1)*self.words].iter().map(|w| w.count_ones() as usize).sum()
+    }
+    fn set_word(&mut self, index : usize, value : u64)
+    {
+        self.trail.push((index, self.domains[index]));
+        self.domains[index] = value;
+    }
+    // narrows a cell down to one tile. all of its words go on the trail even if they don't change, so whatever reads
+    // the trail sees that the cell got decided
+    pub fn assign(&mut self, cell : usize, id : TileId)
+    {
+        for other in self.tiles(cell)
+        {
+            if other != id
+            {
+                self.remove(cell, other);
+            }
+        }
+        for index in cell*self.words..(cell+1)*self.words
+        {
+            self.set_word(index, self.domains[index]);
+        }
+    }
+    pub fn remove(&mut self, cell : usize, id : TileId)
+    {
+        if self.has(cell, id)
+        {
+            let index = cell*self.words + id/64;
+            self.set_word(index, self.domains[index] & !(1 << (id%64)));
+            self.pending.push((cell, id));
+        }
+    }
+    // removes every tile of an undecided cell that has no support from some neighbor; for the first pass, after which
+    // propagating removals keeps it that way
+    pub fn remove_unsupported(&mut self, types : &[TileType])
+    {
+        for (cell, tile_type) in types.iter().enumerate()
+        {
+            if *tile_type != TileType::Field
+            {
+                continue;
+            }
+            for id in self.tiles(cell)
+            {
+                let start = (cell*self.max_index + id)*DIRECTIONS.len();
+                if self.support[start..start + DIRECTIONS.len()].contains(&0)
+                {
+                    self.remove(cell, id);
+                }
+            }
+        }
+    }
+    // where to undo back to
+    pub fn mark(&self) -> (usize, usize)
+    {
+        (self.trail.len(), self.support_trail.len())
+    }
+    pub fn undo(&mut self, (mark, support_mark) : (usize, usize))
+    {
+        while self.trail.len() > mark
+        {
+            let (index, value) = self.trail.pop().unwrap();
+            self.domains[index] = value;
+        }
+        while self.support_trail.len() > support_mark
+        {
+            let index = self.support_trail.pop().unwrap();
+            self.support[index] += 1;
+        }
+        self.pending.clear();
+    }
+    // (word index, value before the trail started) of every word on it, by index
+    pub fn trail_origins(&self) -> Vec<(usize, u64)>
+    {
+        let mut origins = self.trail.clone();
+        // stable, so the first change to each word stays in front of the later ones
+        origins.sort_by_key(|(index, _)| *index);
+        origins.dedup_by_key(|(index, _)| *index);
+        origins
+    }
+    pub fn forget_trail(&mut self)
+    {
+        self.trail.clear();
+        self.support_trail.clear();
+    }
+    // for checkpoints, which are only taken between collapses, when nothing is pending or waiting to be undone
+    pub fn domains(&self) -> &[u64]
+    {
+        &self.domains
+    }
+    pub fn set_domains(&mut self, domains : &[u64], types : &[TileType])
+    {
+        assert!(domains.len() == self.domains.len(), "the checkpoint's possibilities are for a different map size or tileset");
+        self.domains.copy_from_slice(domains);
+        self.forget_trail();
+        self.count_support(types);
+    }
+    // takes the support of every pending removal away from the neighbors, removing what runs out of it, until nothing's
+    // pending. an undecided cell left with nothing is an error unless kill is set, in which case it's marked dead and
+    // stops constraining anything
+    pub fn propagate(&mut self, types : &mut [TileType], kill : bool) -> Result<(), usize>
+    {
+        let words = self.words;
+        while let Some((cell, id)) = self.pending.pop()
+        {
+            if types[cell] == TileType::Dead
+            {
+                continue;
+            }
+            for dir in 0..DIRECTIONS.len()
+            {
+                let neighbor = self.neighbor(cell, dir);
+                // only undecided cells get narrowed, so nothing else's support needs keeping track of
+                if types[neighbor] != TileType::Field
+                {
+                    continue;
+                }
+                let start = (id*DIRECTIONS.len() + dir)*words;
+                for w in 0..words
+                {
+                    // the counts of tiles the neighbor doesn't have anymore don't matter, so they're left alone
+                    let mut bits = self.backing[start + w] & self.domains[neighbor*words + w];
+                    while bits != 0
+                    {
+                        let a = w*64 + bits.trailing_zeros() as usize;
+                        bits &= bits - 1;
+                        let index = (neighbor*self.max_index + a)*DIRECTIONS.len() + get_opposite_direction(dir);
+                        self.support[index] -= 1;
+                        self.support_trail.push(index);
+                        if self.support[index] != 0
+                        {
+                            continue;
+                        }
+                        self.remove(neighbor, a);
+                        if self.count(neighbor) != 0
+                        {
+                            continue;
+                        }
+                        if kill
+                        {
+                            types[neighbor] = TileType::Dead;
+                            self.killed.push(neighbor);
+                            println!("!!!!---- killed tile at {},{}", neighbor%self.width, neighbor/self.width);
+                        }
+                        else
+                        {
+                            self.pending.clear();
+                            return Err(neighbor);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Collapser
+{
+    // how likely each possible tile is for a cell; 0 for impossible tiles. there are no fields to keep up to date here,
+    // so the fields get worked out on the spot, the way the fields solver recalculates a cell for the first time: a
+    // decided neighbor counts for its edge weight, an undecided one for the edge weights of everything it could still
+    // be, blended in like chaos_blend says
+    fn bitset_weights(&self, (x, y) : (isize, isize)) -> Vec<f64>
+    {
+        let state = self.possibilities.as_ref().unwrap();
+        let (chaos_floor, chaos_decay, chaos_blend) = (self.settings.chaos_floor, self.settings.chaos_decay, self.settings.chaos_blend);
+        let neighbors = DIRECTIONS.map(|offset| (self.get_type(x + offset.0, y + offset.1), state.tiles(state.cell(x + offset.0, y + offset.1))));
+        let mut fields = vec!(0.0; DIRECTIONS.len()*self.max_index);
+        let mut weights = vec!(0.0; self.max_index);
+        for id in state.tiles(state.cell(x, y))
+        {
+            for (dir, (neighbor_type, neighbor_tiles)) in neighbors.iter().enumerate()
+            {
+                let opposite = get_opposite_direction(dir);
+                let mut modifier = match neighbor_type
+                {
+                    TileType::Field => neighbor_tiles.iter().map(|b| self.edge_weight(*b, id, opposite)).sum(),
+                    _ => self.actual_weight((*neighbor_type, &[]), id, opposite),
+                };
+                if modifier != 0.0
+                {
+                    modifier = f64::max(modifier, self.settings.min_modifier);
+                    if !neighbor_type.is_real()
+                    {
+                        modifier = chaos_floor + chaos_decay + modifier*chaos_blend;
+                    }
+                }
+                fields[id*DIRECTIONS.len() + dir] = modifier;
+            }
+            weights[id] = self.get_weight(&fields, id);
+        }
+        weights
+    }
+    // adds the undecided neighbors of a cell that just stopped being undecided to the candidates
+    fn push_bitset_candidates(&mut self, (x, y) : (isize, isize))
+    {
+        for offset in DIRECTIONS.iter()
+        {
+            let neighbor = (x + offset.0, y + offset.1);
+            let cell = self.possibilities.as_ref().unwrap().cell(neighbor.0, neighbor.1);
+            let neighbor = ((cell%self.width) as isize, (cell/self.width) as isize);
+            if self.out_map_types[cell] == TileType::Field && !self.candidates.contains(neighbor)
+            {
+                self.candidates.push(neighbor);
+            }
+        }
+    }
+    // propagates whatever is pending, killing cells that run out of tiles, and queues up the neighbors of those
+    pub(crate) fn propagate_bitset(&mut self)
+    {
+        self.possibilities.as_mut().unwrap().propagate(&mut self.out_map_types, true).unwrap();
+        if self.tracks_minimums()
+        {
+            let change = self.trail_possible_change();
+            self.apply_possible_change(&change);
+        }
+        let state = self.possibilities.as_mut().unwrap();
+        state.forget_trail();
+        let killed = std::mem::take(&mut state.killed);
+        for cell in killed
+        {
+            self.push_bitset_candidates(((cell%self.width) as isize, (cell/self.width) as isize));
+        }
+    }
+    // decides one cell; returns whether the decision failed and the cell needs another go, like collapse
+    pub(crate) fn collapse_bitset<R : rng::Rng>(&mut self, collapse_iteration : usize, choice : (isize, isize), rng : &mut R) -> bool
+    {
+        let cell = self.possibilities.as_ref().unwrap().cell(choice.0, choice.1);
+        if self.out_map_types[cell] != TileType::Field
+        {
+            return false;
+        }
+        let options = self.possibilities.as_ref().unwrap().count(cell);
+        let weights = self.bitset_weights(choice);
+        let mut decision = self.sample(&weights, rng);
+        if decision == 0
+        {
+            // every possible tile's weight underflowed or was corrected down to nothing, unless they're all capped
+            match self.possibilities.as_ref().unwrap().tiles(cell).into_iter().find(|id| !self.is_capped(*id))
+            {
+                Some(id) => decision = id,
+                None =>
+                {
+                    self.uncount_cell(cell);
+                    self.out_map_types[cell] = TileType::Dead;
+                    println!("!!!!---- killed tile at {},{}", choice.0, choice.1);
+                    self.push_bitset_candidates(choice);
+                    return false;
+                }
+            }
+        }
+        let force = options < 2;
+
+        let state = self.possibilities.as_mut().unwrap();
+        let mark = state.mark();
+        self.out_map_types[cell] = TileType::Tile(decision);
+        state.assign(cell, decision);
+        let mut result = state.propagate(&mut self.out_map_types, force);
+        if result.is_ok() && !force && self.tracks_minimums() && self.breaks_minimums(&self.trail_possible_change(), decision)
+        {
+            println!("!!!--- tile {} at {},{} would leave too few cells for a minimum count", decision, choice.0, choice.1);
+            result = Err(cell);
+        }
+        let state = self.possibilities.as_mut().unwrap();
+        match result
+        {
+            Ok(()) =>
+            {
+                if force && !state.killed.is_empty()
+                {
+                    println!("!!!--- failed with tile {} at {},{}, but forced to live with it", decision, choice.0, choice.1);
+                }
+                self.propagate_bitset();
+                self.push_bitset_candidates(choice);
+                self.add_to_freq(decision);
+                self.count_placed_tile(decision);
+                let comp = std::cmp::max(1, ((self.width*self.height) as f32/8.0).floor() as usize);
+                if collapse_iteration.is_multiple_of(comp)
+                {
+                    println!("writing image for {}", collapse_iteration);
+                    self.write_image(collapse_iteration.to_string(), choice);
+                    println!("wrote image");
+                }
+                false
+            }
+            Err(_) =>
+            {
+                println!("!!!--- failed with tile {} at {},{}, retrying", decision, choice.0, choice.1);
+                state.undo(mark);
+                self.out_map_types[cell] = TileType::Field;
+                state.remove(cell, decision);
+                self.propagate_bitset();
+                true
+            }
+        }
+    }
+    // the first pass over whatever's pinned, before anything gets collapsed
+    pub(crate) fn begin_bitset(&mut self)
+    {
+        // the fields solver would start recalculating from what init_edges and pinning marked as damaged. here the
+        // possibilities start from everything and lose whatever nothing around supports, wherever it is
+        self.damage.clear();
+        self.possibilities.as_mut().unwrap().remove_unsupported(&self.out_map_types);
+        self.propagate_bitset();
+        for y in 0..self.height as isize
+        {
+            for x in 0..self.width as isize
+            {
+                if self.get_type(x, y) != TileType::Field
+                {
+                    self.push_bitset_candidates((x, y));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{frontier, rng, settings, Collapser, TileType};
+use std::convert::TryInto;
+
+// a run's whole state between two collapses, so it can be stopped and picked up again later, or a run that goes wrong
+// can be handed to someone else and replayed from just before it does. the learned model, settings and constraints
+// aren't in it: they come from the example and command line again, and have to be the same as when it was saved (the
+// output size and solver, and fingerprints of the model and of the settings and constraints, are checked)
+const MAGIC : &[u8] = b"collapse checkpoint\0";
+const VERSION : u32 = 1;
+
+const FIELD_CELL : i64 = -2;
+const DEAD_CELL : i64 = -1;
+
+struct Writer
+{
+    bytes : Vec<u8>,
+}
+
+impl Writer
+{
+    fn u64(&mut self, value : u64)
+    {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    fn i64(&mut self, value : i64)
+    {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+    fn f64(&mut self, value : f64)
+    {
+        self.u64(value.to_bits());
+    }
+    fn len(&mut self, len : usize)
+    {
+        self.u64(len as u64);
+    }
+    fn bytes(&mut self, bytes : &[u8])
+    {
+        self.len(bytes.len());
+        self.bytes.extend_from_slice(bytes);
+    }
+}
+
+struct Reader<'a>
+{
+    bytes : &'a [u8],
+}
+
+impl<'a> Reader<'a>
+{
+    fn take<const N : usize>(&mut self) -> [u8; N]
+    {
+        assert!(self.bytes.len() >= N, "the checkpoint is cut off");
+        let (head, rest) = self.bytes.split_at(N);
+        self.bytes = rest;
+        head.try_into().unwrap()
+    }
+    fn u64(&mut self) -> u64
+    {
+        u64::from_le_bytes(self.take())
+    }
+    fn i64(&mut self) -> i64
+    {
+        i64::from_le_bytes(self.take())
+    }
+    fn f64(&mut self) -> f64
+    {
+        f64::from_bits(self.u64())
+    }
+    fn len(&mut self) -> usize
+    {
+        let len = self.u64() as usize;
+        // every element is at least a byte, so this catches garbage before it turns into a huge allocation
+        assert!(len <= self.bytes.len(), "the checkpoint is cut off");
+        len
+    }
+    fn bytes(&mut self) -> &'a [u8]
+    {
+        let len = self.len();
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        head
+    }
+}
+
+fn fnv1a(bytes : impl Iterator<Item = u8>) -> u64
+{
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for byte in bytes
+    {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+impl Collapser
+{
+    // over the learned neighbor weights, to catch checkpoints from a different example
+    fn model_fingerprint(&self) -> u64
+    {
+        fnv1a(self.ships.iter().flat_map(|ship| ship.to_bits().to_le_bytes()))
+    }
+    // over everything else that changes what the rest of the run does: settings, count constraints and walkability.
+    // any thread count above 1 makes the same maps, and min_parallel_work doesn't change them at all
+    fn setup_fingerprint(&self) -> u64
+    {
+        let mut settings = self.settings.clone();
+        settings.threads = std::cmp::min(settings.threads, 2);
+        settings.min_parallel_work = 0;
+        let setup = format!("{:?} {:?} {:?} {:?}", settings, self.count_constraints, self.walkable, self.required_walkable);
+        fnv1a(setup.bytes())
+    }
+    // collapse_iteration is the next collapse's, as continue_run hands it out
+    pub fn save_checkpoint<R : rng::Rng + ?Sized>(&self, path : &str, rng : &R, collapse_iteration : usize)
+    {
+        assert!(self.damage.is_empty(), "checkpoints can only be taken between collapses");
+        let mut out = Writer { bytes : MAGIC.to_vec() };
+        out.bytes.extend_from_slice(&VERSION.to_le_bytes());
+        out.u64(self.width as u64);
+        out.u64(self.height as u64);
+        out.u64(self.max_index as u64);
+        out.u64(self.settings.solver as u64);
+        out.u64(self.model_fingerprint());
+        out.u64(self.setup_fingerprint());
+        out.u64(collapse_iteration as u64);
+        out.bytes(&rng.save_state());
+        out.len(self.out_map_types.len());
+        for tile_type in self.out_map_types.iter()
+        {
+            out.i64(match tile_type
+            {
+                TileType::Tile(id) => *id as i64,
+                TileType::Field => FIELD_CELL,
+                TileType::Dead => DEAD_CELL,
+            });
+        }
+        out.len(self.out_map_fields.len());
+        self.out_map_fields.iter().for_each(|f| out.f64(*f));
+        let candidates = self.candidates.cells();
+        out.len(candidates.len());
+        for (x, y) in candidates
+        {
+            out.i64(x as i64);
+            out.i64(y as i64);
+        }
+        out.len(self.out_freqs.len());
+        self.out_freqs.iter().for_each(|f| out.f64(*f));
+        out.f64(self.out_total_freq);
+        out.len(self.tile_counts.len());
+        self.tile_counts.iter().for_each(|count| out.u64(*count as u64));
+        let domains = self.possibilities.as_ref().map(|state| state.domains()).unwrap_or(&[]);
+        out.len(domains.len());
+        domains.iter().for_each(|word| out.u64(*word));
+        // written next to it and renamed over it, so being stopped partway through leaves the last checkpoint whole
+        let tmp_path = format!("{}.tmp", path);
+        std::fs::write(&tmp_path, out.bytes).unwrap_or_else(|e| panic!("failed to write checkpoint {}: {}", tmp_path, e));
+        std::fs::rename(&tmp_path, path).unwrap_or_else(|e| panic!("failed to move checkpoint {} to {}: {}", tmp_path, path, e));
+    }
+    // replaces the run state of an output that's been set up the same way as the one the checkpoint was taken from;
+    // puts the rng back where it was and returns the next collapse's iteration, to hand both to continue_run
+    pub fn load_checkpoint<R : rng::Rng + ?Sized>(&mut self, path : &str, rng : &mut R) -> usize
+    {
+        let bytes = std::fs::read(path).unwrap_or_else(|e| panic!("failed to read checkpoint {}: {}", path, e));
+        assert!(bytes.starts_with(MAGIC), "{} isn't a checkpoint", path);
+        let mut input = Reader { bytes : &bytes[MAGIC.len()..] };
+        let version = u32::from_le_bytes(input.take());
+        assert!(version == VERSION, "{} is a version {} checkpoint, but only version {} is supported", path, version, VERSION);
+        let (width, height, max_index) = (input.u64() as usize, input.u64() as usize, input.u64() as usize);
+        assert!((width, height) == (self.width, self.height), "the checkpoint is of a {}x{} map, but the output is {}x{}", width, height, self.width, self.height);
+        assert!(max_index == self.max_index && input.u64() == self.settings.solver as u64 && input.u64() == self.model_fingerprint(),
+            "the checkpoint was taken with a different example or solver");
+        assert!(input.u64() == self.setup_fingerprint(), "the checkpoint was taken with different settings, count constraints or walkable tiles");
+        let collapse_iteration = input.u64() as usize;
+        let rng_state = input.bytes();
+
+        let len = input.len();
+        assert!(len == self.out_map_types.len(), "the checkpoint has the wrong number of cells");
+        for i in 0..len
+        {
+            self.out_map_types[i] = match input.i64()
+            {
+                FIELD_CELL => TileType::Field,
+                DEAD_CELL => TileType::Dead,
+                id if id >= 0 && (id as usize) < self.max_index => TileType::Tile(id as usize),
+                id => panic!("the checkpoint has a bad tile {}", id),
+            };
+        }
+        let len = input.len();
+        assert!(len == self.out_map_fields.len(), "the checkpoint's fields are for a different map size or solver");
+        for i in 0..len
+        {
+            self.out_map_fields[i] = input.f64();
+        }
+        self.candidates = frontier::Frontier::new(self.width, self.height);
+        for _ in 0..input.len()
+        {
+            let cell = (input.i64() as isize, input.i64() as isize);
+            self.candidates.push(cell);
+        }
+        self.damage.clear();
+        let len = input.len();
+        self.out_freqs = (0..len).map(|_| input.f64()).collect();
+        self.out_total_freq = input.f64();
+        let len = input.len();
+        self.tile_counts = (0..len).map(|_| input.u64() as usize).collect();
+        assert!(self.out_freqs.len() == self.max_index && self.tile_counts.len() == self.max_index, "the checkpoint's tile counts are for a different tileset");
+        let len = input.len();
+        let domains = (0..len).map(|_| input.u64()).collect::<Vec<_>>();
+        match self.settings.solver
+        {
+            settings::Solver::Bitset => self.possibilities.as_mut().unwrap().set_domains(&domains, &self.out_map_types),
+            settings::Solver::Fields => assert!(domains.is_empty(), "the checkpoint was taken with the bitset solver"),
+        }
+        assert!(input.bytes.is_empty(), "the checkpoint has junk at the end");
+        rng.restore_state(rng_state);
+        collapse_iteration
+    }
+    // runs like run (or picks up from a checkpoint instead of starting over), saving a checkpoint to checkpoint_path
+    // every `every` collapses if that's set
+    pub fn run_checkpointed(&mut self, seed : u128, resume : Option<&str>, checkpoint_path : &str, every : Option<usize>)
+    {
+        let mut rng = oorandom::Rand64::new(seed);
+        let collapse_iteration = match resume
+        {
+            Some(path) => self.load_checkpoint(path, &mut rng),
+            None => self.begin_run(),
+        };
+        self.continue_run(&mut rng, collapse_iteration, &mut |collapser, rng, collapse_iteration|
+        {
+            if every.is_some_and(|every| collapse_iteration%every == 0)
+            {
+                collapser.save_checkpoint(checkpoint_path, rng, collapse_iteration);
+            }
+        });
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{Collapser, TileId, TileType, DIRECTIONS};
+use std::collections::{HashMap, VecDeque};
+
+// how many times a run that left the walkable area split starts over before settling for it
+pub(crate) const CONNECTIVITY_RESTARTS : usize = 16;
+
+impl Collapser
+{
+    pub fn set_walkable(&mut self, tiles : &[TileId])
+    {
+        assert!(self.possibilities.is_none(), "the bitset solver doesn't support walkable areas");
+        self.walkable = vec!(false; self.max_index);
+        for tile in tiles
+        {
+            assert!(*tile < self.max_index, "walkable tile {} doesn't exist, there are only {} tiles", tile, self.max_index);
+            self.walkable[*tile] = true;
+        }
+    }
+    // makes a cell walkable by removing every other possibility from it; walkable cells must all connect,
+    // so requiring two of them amounts to requiring a path between them
+    pub fn require_walkable(&mut self, (x, y) : (isize, isize))
+    {
+        assert!(!self.walkable.is_empty(), "pick walkable tiles before requiring a path");
+        assert!(x >= 0 && y >= 0 && (x as usize) < self.width && (y as usize) < self.height, "{},{} is outside of the map", x, y);
+        match self.get_type(x, y)
+        {
+            TileType::Tile(id) => assert!(self.walkable[id], "{},{} is pinned to tile {}, which isn't walkable", x, y, id),
+            TileType::Dead => panic!("{},{} is dead", x, y),
+            TileType::Field =>
+            {
+                for id in 0..self.max_index
+                {
+                    if !self.walkable[id]
+                    {
+                        self.get_all_fields_mut(x, y)[id*DIRECTIONS.len()..(id+1)*DIRECTIONS.len()].fill(0.0);
+                    }
+                }
+                self.damage.push((x, y));
+                for dir in DIRECTIONS.iter()
+                {
+                    self.damage.push((x+dir.0, y+dir.1));
+                }
+            }
+        }
+        self.required_walkable.push((x, y));
+    }
+    fn is_walkable(&self, x : isize, y : isize) -> bool
+    {
+        match self.get_type(x, y)
+        {
+            TileType::Tile(id) => self.walkable[id],
+            _ => false
+        }
+    }
+    fn could_be_walkable(&self, x : isize, y : isize) -> bool
+    {
+        match self.get_type(x, y)
+        {
+            TileType::Tile(id) => self.walkable[id],
+            TileType::Field =>
+            {
+                let fields = self.get_all_fields(x, y);
+                (0..self.max_index).any(|id| self.walkable[id] && fields[id*DIRECTIONS.len()..(id+1)*DIRECTIONS.len()].iter().all(|f| *f != 0.0))
+            }
+            TileType::Dead => false
+        }
+    }
+    // walkable or required to be; these are what has to stay connected
+    fn is_anchor(&self, x : isize, y : isize) -> bool
+    {
+        self.is_walkable(x, y) || self.required_walkable.contains(&(x, y))
+    }
+    fn is_open(&self, x : isize, y : isize) -> bool
+    {
+        self.could_be_walkable(x, y) || self.required_walkable.contains(&(x, y))
+    }
+    // the same as is_open, from a cell's type and fields as the journal recorded them
+    fn was_open(&self, cell : usize, tile_type : TileType, fields : &[f64]) -> bool
+    {
+        let open = match tile_type
+        {
+            TileType::Tile(id) => self.walkable[id],
+            TileType::Field => (0..self.max_index).any(|id| self.walkable[id] && crate::constraints::fields_allow(fields, id)),
+            TileType::Dead => false
+        };
+        open || self.required_walkable.contains(&((cell%self.width) as isize, (cell/self.width) as isize))
+    }
+    // sets walkable_anchors (or walkable_pieces) from scratch; the one full pass over the map, done before collapsing
+    // starts and after a forced decision
+    pub(crate) fn count_walkable_anchors(&mut self)
+    {
+        self.walkable_anchors = None;
+        if self.walkable.is_empty()
+        {
+            return;
+        }
+        self.walkable_pieces = self.walkable_components();
+        if self.walkable_pieces > 1
+        {
+            return;
+        }
+        let (width, height) = (self.width as isize, self.height as isize);
+        self.walkable_anchors = Some((0..height).flat_map(|y| (0..width).map(move |x| (x, y))).filter(|(x, y)| self.is_anchor(*x, *y)).count());
+    }
+    // checks the collapse in progress against the walkable area, from the cells the journal says it changed: cells
+    // that can no longer be walkable might cut the area apart, and cells that just became walkable have to join it.
+    // returns how many cells became walkable, or None if the area would end up split
+    pub(crate) fn walkable_change(&self) -> Option<usize>
+    {
+        if self.walkable.is_empty()
+        {
+            return Some(0);
+        }
+        let Some(anchors) = self.walkable_anchors else
+        {
+            // already split, so there's no one piece to keep together, just no more pieces than there are. that takes
+            // counting them all again, but only on maps that can't come out connected anyway
+            return if self.walkable_components() <= self.walkable_pieces { Some(0) } else { None };
+        };
+        let stride = DIRECTIONS.len()*self.max_index;
+        let (width, height) = (self.width as isize, self.height as isize);
+        let mut starts = Vec::new();
+        let mut new_anchors = Vec::new();
+        for (cell, old_type, old_fields) in self.journal.entries(stride)
+        {
+            let (x, y) = ((cell%self.width) as isize, (cell/self.width) as isize);
+            if self.is_anchor(x, y) && !(matches!(old_type, TileType::Tile(id) if self.walkable[id]) || self.required_walkable.contains(&(x, y)))
+            {
+                new_anchors.push(cell);
+                starts.push(cell);
+            }
+            if self.was_open(cell, old_type, old_fields) && !self.is_open(x, y)
+            {
+                for dir in DIRECTIONS.iter()
+                {
+                    let (nx, ny) = (x + dir.0, y + dir.1);
+                    if nx >= 0 && ny >= 0 && nx < width && ny < height && self.is_open(nx, ny)
+                    {
+                        starts.push((ny*width + nx) as usize);
+                    }
+                }
+            }
+        }
+        if self.stays_connected(&starts, &new_anchors, anchors)
+        {
+            Some(new_anchors.len())
+        }
+        else
+        {
+            None
+        }
+    }
+    // for journaled changes that weren't decisions and so can't fail, like invalidating one: if they cut the walkable
+    // area anyway, it's split from here on
+    pub(crate) fn update_walkable(&mut self)
+    {
+        match self.walkable_change()
+        {
+            Some(count) =>
+            {
+                if let Some(anchors) = self.walkable_anchors.as_mut()
+                {
+                    *anchors += count;
+                }
+            }
+            None => self.count_walkable_anchors(),
+        }
+    }
+    // whether the open cells around what just closed are still in one piece as far as the anchors go, and the new
+    // anchors are in that piece too. the old anchors, of which there are given many, all used to be in one piece.
+    // searches from every start at once, merging searches that meet, until at most one is still going and it has
+    // found an old anchor if it needs one: the ones that finished are whole pieces, and the one left over holds
+    // whatever old anchors they didn't find. a collapse that didn't cut anything only costs a walk around it
+    fn stays_connected(&self, starts : &[usize], new_anchors : &[usize], anchors : usize) -> bool
+    {
+        fn find(parent : &mut [usize], mut i : usize) -> usize
+        {
+            while parent[i] != i
+            {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        let (width, height) = (self.width as isize, self.height as isize);
+        let is_old_anchor = |cell : usize| self.is_anchor((cell%self.width) as isize, (cell/self.width) as isize) && !new_anchors.contains(&cell);
+        let mut owner = HashMap::new();
+        let mut parent = Vec::new();
+        let mut queues = Vec::new();
+        // per search, how many old anchors it found and whether it started from a new one
+        let mut found = Vec::new();
+        let mut fresh = Vec::new();
+        for start in starts
+        {
+            if owner.contains_key(start)
+            {
+                continue;
+            }
+            owner.insert(*start, parent.len());
+            found.push(is_old_anchor(*start) as usize);
+            fresh.push(new_anchors.contains(start));
+            parent.push(parent.len());
+            queues.push(VecDeque::from(vec!(*start)));
+        }
+        loop
+        {
+            let active = (0..parent.len()).filter(|i| parent[*i] == *i && !queues[*i].is_empty()).collect::<Vec<_>>();
+            if active.len() <= 1 && active.iter().all(|i| !fresh[*i] || found[*i] > 0 || anchors == 0)
+            {
+                break;
+            }
+            for i in active
+            {
+                if parent[i] != i
+                {
+                    continue;
+                }
+                let Some(cell) = queues[i].pop_front() else { continue };
+                let (x, y) = ((cell%self.width) as isize, (cell/self.width) as isize);
+                for dir in DIRECTIONS.iter()
+                {
+                    let (nx, ny) = (x + dir.0, y + dir.1);
+                    if nx < 0 || ny < 0 || nx >= width || ny >= height || !self.is_open(nx, ny)
+                    {
+                        continue;
+                    }
+                    let neighbor = (ny*width + nx) as usize;
+                    match owner.get(&neighbor)
+                    {
+                        None =>
+                        {
+                            owner.insert(neighbor, i);
+                            found[i] += is_old_anchor(neighbor) as usize;
+                            queues[i].push_back(neighbor);
+                        }
+                        Some(j) =>
+                        {
+                            let j = find(&mut parent, *j);
+                            if j != i
+                            {
+                                parent[j] = i;
+                                found[i] += found[j];
+                                fresh[i] |= fresh[j];
+                                let queue = std::mem::take(&mut queues[j]);
+                                queues[i].extend(queue);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        let roots = (0..parent.len()).filter(|i| parent[*i] == *i).collect::<Vec<_>>();
+        let finished = roots.iter().filter(|i| queues[**i].is_empty()).copied().collect::<Vec<_>>();
+        let leftover = roots.iter().find(|i| !queues[**i].is_empty()).copied();
+        let leftover_anchors = anchors - finished.iter().map(|i| found[*i]).sum::<usize>();
+        // pieces that hold anchors of either kind; with old anchors around, new ones also have to be with them
+        let anchored = finished.iter().filter(|i| found[**i] > 0 || fresh[**i]).count() + leftover.is_some_and(|i| leftover_anchors > 0 || fresh[i]) as usize;
+        let stranded = anchors > 0 && finished.iter().any(|i| fresh[*i] && found[*i] == 0);
+        anchored <= 1 && !stranded
+    }
+    // how many separate areas the walkable cells (decided or required) fall into, if every undecided cell
+    // that could still become walkable does; this only ever goes up as cells get decided. a full pass over the map,
+    // so collapses check walkable_change instead. does not wrap around the edges of the map, unlike the solver
+    pub(crate) fn walkable_components(&self) -> usize
+    {
+        if self.walkable.is_empty()
+        {
+            return 0;
+        }
+        let (width, height) = (self.width as isize, self.height as isize);
+        let mut visited = vec!(false; self.width*self.height);
+        let mut open = vec!(false; self.width*self.height);
+        for y in 0..height
+        {
+            for x in 0..width
+            {
+                open[(y*width + x) as usize] = self.could_be_walkable(x, y);
+            }
+        }
+        let mut components = 0;
+        let mut queue = VecDeque::new();
+        for y in 0..height
+        {
+            for x in 0..width
+            {
+                let index = (y*width + x) as usize;
+                if visited[index] || !(self.is_walkable(x, y) || self.required_walkable.contains(&(x, y)))
+                {
+                    continue;
+                }
+                components += 1;
+                visited[index] = true;
+                queue.push_back((x, y));
+                while let Some((cx, cy)) = queue.pop_front()
+                {
+                    for dir in DIRECTIONS.iter()
+                    {
+                        let (nx, ny) = (cx + dir.0, cy + dir.1);
+                        if nx < 0 || ny < 0 || nx >= width || ny >= height
+                        {
+                            continue;
+                        }
+                        let neighbor = (ny*width + nx) as usize;
+                        if open[neighbor] && !visited[neighbor]
+                        {
+                            visited[neighbor] = true;
+                            queue.push_back((nx, ny));
+                        }
+                    }
+                }
+            }
+        }
+        components
+    }
+    // prints a complaint if the walkable area ended up split; returns whether it's in one piece
+    pub fn check_connectivity(&self) -> bool
+    {
+        let components = self.walkable_components();
+        if components > 1
+        {
+            println!("!!!!---- the walkable area is split into {} separate pieces", components);
+        }
+        components <= 1
+    }
+}
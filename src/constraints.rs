@@ -0,0 +1,299 @@
+use crate::{Collapser, TileId, TileType, DIRECTIONS};
+
+// a hard limit on how many cells of the output may hold a given tile
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct CountConstraint
+{
+    pub tile : TileId,
+    pub min : usize,
+    pub max : usize,
+}
+
+impl CountConstraint
+{
+    // "tile:min:max", where either bound can be left empty, e.g. "7:1:1", "3:3:", "12::5"
+    pub fn parse(text : &str) -> CountConstraint
+    {
+        let parts = text.split(':').collect::<Vec<_>>();
+        assert!(parts.len() == 3, "tile count constraints look like tile:min:max, got {}", text);
+        let tile = parts[0].parse().unwrap_or_else(|_| panic!("bad tile id in count constraint {}", text));
+        let min = if parts[1].is_empty() { 0 } else { parts[1].parse().unwrap_or_else(|_| panic!("bad minimum in count constraint {}", text)) };
+        let max = if parts[2].is_empty() { usize::MAX } else { parts[2].parse().unwrap_or_else(|_| panic!("bad maximum in count constraint {}", text)) };
+        assert!(min <= max, "count constraint {} has a minimum above its maximum", text);
+        CountConstraint { tile, min, max }
+    }
+}
+
+// how much more likely a tile is to be picked while its minimum isn't met yet. that only gets minimums met sooner;
+// what actually enforces them is that a decision leaving too few cells for one counts as a contradiction
+const UNMET_MINIMUM_BOOST : f64 = 1000.0;
+
+// whether a cell with these fields could still be the given tile
+pub(crate) fn fields_allow(fields : &[f64], id : TileId) -> bool
+{
+    fields[id*DIRECTIONS.len()..(id+1)*DIRECTIONS.len()].iter().all(|f| *f != 0.0)
+}
+
+// what a collapse did to possible_counts and undecided, before it's kept or rolled back
+pub(crate) struct PossibleChange
+{
+    counts : Vec<isize>,
+    undecided : isize,
+}
+
+impl Collapser
+{
+    pub fn add_count_constraint(&mut self, constraint : CountConstraint)
+    {
+        assert!(constraint.tile < self.max_index, "count constraint on tile {}, but there are only {} tiles", constraint.tile, self.max_index);
+        self.count_constraints.push(constraint);
+        let undecided = self.out_map_types.iter().filter(|t| **t == TileType::Field).count();
+        let needed = self.count_constraints.iter().map(|c| c.min.saturating_sub(self.tile_counts[c.tile])).sum::<usize>();
+        if needed > undecided
+        {
+            println!("!!!!---- tile count constraints need at least {} more tiles, but there are only {} cells to fill", needed, undecided);
+        }
+    }
+    // capped tiles have already been taken out of every undecided cell by ban_tile; they're left out here too so that
+    // sampling can never pick one whatever the fields say
+    pub(crate) fn get_count_multiplier(&self, id : TileId) -> f64
+    {
+        let mut multiplier = 1.0;
+        for constraint in &self.count_constraints
+        {
+            if constraint.tile != id
+            {
+                continue;
+            }
+            if self.tile_counts[id] >= constraint.max
+            {
+                return 0.0;
+            }
+            if self.tile_counts[id] < constraint.min
+            {
+                multiplier = UNMET_MINIMUM_BOOST;
+            }
+        }
+        multiplier
+    }
+    pub(crate) fn is_capped(&self, id : TileId) -> bool
+    {
+        self.count_constraints.iter().any(|c| c.tile == id && self.tile_counts[id] >= c.max)
+    }
+    // to be called whenever a cell gets decided
+    pub(crate) fn count_placed_tile(&mut self, id : TileId)
+    {
+        self.tile_counts[id] += 1;
+        if self.count_constraints.iter().any(|c| c.tile == id && self.tile_counts[id] == c.max)
+        {
+            println!("tile {} reached its maximum count, no more will be placed", id);
+            self.ban_tile(id);
+        }
+    }
+    // takes a tile out of every undecided cell that could still hold it, through the journal or the trail so the
+    // minimum counts and the walkable area keep up. cells left with nothing die
+    pub(crate) fn ban_tile(&mut self, id : TileId)
+    {
+        let cells = self.width*self.height;
+        if let Some(state) = self.possibilities.as_mut()
+        {
+            for cell in 0..cells
+            {
+                if self.out_map_types[cell] != TileType::Field || !state.has(cell, id)
+                {
+                    continue;
+                }
+                state.remove(cell, id);
+                if state.count(cell) == 0
+                {
+                    self.out_map_types[cell] = TileType::Dead;
+                    state.killed.push(cell);
+                    println!("!!!!---- killed tile at {},{}", cell%self.width, cell/self.width);
+                }
+            }
+            self.propagate_bitset();
+            return;
+        }
+        let stride = DIRECTIONS.len()*self.max_index;
+        self.begin_journal();
+        for cell in 0..cells
+        {
+            if !self.cell_could_hold(cell, id)
+            {
+                continue;
+            }
+            let (x, y) = ((cell%self.width) as isize, (cell/self.width) as isize);
+            self.get_all_fields_mut(x, y)[id*DIRECTIONS.len()..(id+1)*DIRECTIONS.len()].fill(0.0);
+            if !(0..self.max_index).any(|other| fields_allow(&self.out_map_fields[cell*stride..(cell+1)*stride], other))
+            {
+                self.out_map_types[cell] = TileType::Dead;
+                println!("!!!!---- killed tile at {},{}", x, y);
+            }
+            self.damage.push((x, y));
+            for dir in DIRECTIONS.iter()
+            {
+                self.damage.push((x+dir.0, y+dir.1));
+            }
+        }
+        let mut scratch_fields = vec!(1.0; stride);
+        self.recalculate_all(&mut scratch_fields, 0, false, 0);
+        self.update_walkable();
+        self.end_journal();
+    }
+    pub(crate) fn tracks_minimums(&self) -> bool
+    {
+        self.count_constraints.iter().any(|c| c.min > 0)
+    }
+    pub(crate) fn cell_could_hold(&self, cell : usize, id : TileId) -> bool
+    {
+        if self.out_map_types[cell] != TileType::Field
+        {
+            return false;
+        }
+        match &self.possibilities
+        {
+            Some(state) => state.has(cell, id),
+            None =>
+            {
+                let stride = DIRECTIONS.len()*self.max_index;
+                fields_allow(&self.out_map_fields[cell*stride..(cell+1)*stride], id)
+            }
+        }
+    }
+    // counts possible_counts and undecided from scratch; after this, whatever changes cells keeps them up to date
+    pub(crate) fn count_possible(&mut self)
+    {
+        if !self.tracks_minimums()
+        {
+            return;
+        }
+        let cells = self.width*self.height;
+        self.undecided = self.out_map_types.iter().filter(|t| **t == TileType::Field).count();
+        self.possible_counts = self.count_constraints.iter().map(|c| (0..cells).filter(|cell| self.cell_could_hold(*cell, c.tile)).count()).collect();
+    }
+    // for a cell about to be marked dead without going through the journal or the trail
+    pub(crate) fn uncount_cell(&mut self, cell : usize)
+    {
+        if !self.tracks_minimums() || self.out_map_types[cell] != TileType::Field
+        {
+            return;
+        }
+        for k in 0..self.count_constraints.len()
+        {
+            if self.cell_could_hold(cell, self.count_constraints[k].tile)
+            {
+                self.possible_counts[k] -= 1;
+            }
+        }
+        self.undecided -= 1;
+    }
+    // what everything recorded in the journal so far did to the counts
+    pub(crate) fn journal_possible_change(&self) -> PossibleChange
+    {
+        let stride = DIRECTIONS.len()*self.max_index;
+        let mut change = PossibleChange { counts : vec!(0; self.count_constraints.len()), undecided : 0 };
+        for (cell, old_type, old_fields) in self.journal.entries(stride)
+        {
+            let new_type = self.out_map_types[cell];
+            let new_fields = &self.out_map_fields[cell*stride..(cell+1)*stride];
+            change.undecided += (new_type == TileType::Field) as isize - (old_type == TileType::Field) as isize;
+            for (count, constraint) in change.counts.iter_mut().zip(self.count_constraints.iter())
+            {
+                let before = old_type == TileType::Field && fields_allow(old_fields, constraint.tile);
+                let after = new_type == TileType::Field && fields_allow(new_fields, constraint.tile);
+                *count += after as isize - before as isize;
+            }
+        }
+        change
+    }
+    // what everything on the bitset solver's trail did to the counts. every cell on it was undecided before it got there
+    pub(crate) fn trail_possible_change(&self) -> PossibleChange
+    {
+        let state = self.possibilities.as_ref().unwrap();
+        let mut change = PossibleChange { counts : vec!(0; self.count_constraints.len()), undecided : 0 };
+        let mut last_cell = None;
+        for (index, old) in state.trail_origins()
+        {
+            let cell = index/state.words();
+            let word = index%state.words();
+            let alive = self.out_map_types[cell] == TileType::Field;
+            if last_cell != Some(cell)
+            {
+                last_cell = Some(cell);
+                change.undecided -= !alive as isize;
+            }
+            for (count, constraint) in change.counts.iter_mut().zip(self.count_constraints.iter())
+            {
+                if constraint.tile/64 == word
+                {
+                    let before = old >> (constraint.tile%64) & 1 != 0;
+                    let after = alive && state.has(cell, constraint.tile);
+                    *count += after as isize - before as isize;
+                }
+            }
+        }
+        change
+    }
+    pub(crate) fn apply_possible_change(&mut self, change : &PossibleChange)
+    {
+        for (count, delta) in self.possible_counts.iter_mut().zip(change.counts.iter())
+        {
+            *count = count.checked_add_signed(*delta).unwrap();
+        }
+        self.undecided = self.undecided.checked_add_signed(change.undecided).unwrap();
+    }
+    // whether every minimum can still be met given how many cells could still hold each tile; placed is a tile that's
+    // about to be counted but isn't in tile_counts yet
+    fn minimums_reachable(&self, possible : &[usize], undecided : usize, placed : Option<TileId>) -> bool
+    {
+        let mut needed = vec!(0; self.max_index);
+        for (constraint, possible) in self.count_constraints.iter().zip(possible.iter())
+        {
+            let count = self.tile_counts[constraint.tile] + (placed == Some(constraint.tile)) as usize;
+            let missing = constraint.min.saturating_sub(count);
+            if missing > *possible
+            {
+                return false;
+            }
+            needed[constraint.tile] = std::cmp::max(needed[constraint.tile], missing);
+        }
+        needed.iter().sum::<usize>() <= undecided
+    }
+    // whether deciding a cell as this tile, with the given change, takes some minimum from reachable to out of reach.
+    // ones that were already out of reach don't count, or every decision after that would fail. "could hold" only
+    // means propagation hasn't ruled it out yet, so a minimum can still turn out unreachable later; then it's reported
+    // by check_count_constraints like any other contradiction the solver had to live with
+    pub(crate) fn breaks_minimums(&self, change : &PossibleChange, decision : TileId) -> bool
+    {
+        if !self.minimums_reachable(&self.possible_counts, self.undecided, None)
+        {
+            return false;
+        }
+        let possible = self.possible_counts.iter().zip(change.counts.iter()).map(|(count, delta)| count.checked_add_signed(*delta).unwrap()).collect::<Vec<_>>();
+        let undecided = self.undecided.checked_add_signed(change.undecided).unwrap();
+        !self.minimums_reachable(&possible, undecided, Some(decision))
+    }
+    // prints every constraint that ended up violated; returns whether they all held
+    pub fn check_count_constraints(&self) -> bool
+    {
+        let mut ok = true;
+        for constraint in &self.count_constraints
+        {
+            let count = self.tile_counts[constraint.tile];
+            if count < constraint.min
+            {
+                println!("!!!!---- tile {} appears {} times, but at least {} were required", constraint.tile, count, constraint.min);
+                ok = false;
+            }
+            if count > constraint.max
+            {
+                println!("!!!!---- tile {} appears {} times, but at most {} were allowed", constraint.tile, count, constraint.max);
+                ok = false;
+            }
+        }
+        ok
+    }
+}
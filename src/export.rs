@@ -0,0 +1,145 @@
+use crate::{json, Rgba, TileType, TILESIZE};
+use std::fmt::Write;
+
+// what dead (or undecided) cells get written as in the index grid exports
+pub const DEAD_CELL : i64 = -1;
+
+fn cell_index(tile_type : TileType) -> i64
+{
+    match tile_type
+    {
+        TileType::Tile(id) => id as i64,
+        _ => DEAD_CELL
+    }
+}
+
+// lays tiles out left-to-right, top-to-bottom in a roughly square grid
+pub fn atlas_columns(tile_count : usize) -> usize
+{
+    let mut columns = 1;
+    while columns*columns < tile_count
+    {
+        columns += 1;
+    }
+    columns
+}
+pub fn atlas_rows(tile_count : usize) -> usize
+{
+    tile_count.div_ceil(atlas_columns(tile_count))
+}
+
+// writes every tile exactly once, in TileId order, so tile N is the Nth cell of the atlas
+pub fn write_atlas(path : &str, tiles : &[Vec<Rgba>]) -> (usize, usize)
+{
+    let columns = atlas_columns(tiles.len());
+    let rows = atlas_rows(tiles.len());
+    let mut out = image::RgbaImage::new((columns*TILESIZE) as u32, (rows*TILESIZE) as u32);
+    for (id, tile) in tiles.iter().enumerate()
+    {
+        let (x, y) = (id%columns, id/columns);
+        for ty in 0..TILESIZE
+        {
+            for tx in 0..TILESIZE
+            {
+                let px = &tile[ty*TILESIZE + tx];
+                out.put_pixel((x*TILESIZE + tx) as u32, (y*TILESIZE + ty) as u32, image::Rgba([px.r, px.g, px.b, px.a]));
+            }
+        }
+    }
+    out.save(path).unwrap();
+    (columns, rows)
+}
+
+// just the file name, for references from one output file to another written next to it
+pub fn file_name(path : &str) -> &str
+{
+    std::path::Path::new(path).file_name().and_then(|name| name.to_str()).unwrap_or(path)
+}
+
+// draws a finished map; dead cells are left transparent
+pub fn render_grid(types : &[TileType], width : usize, height : usize, id_to_tile : &[Vec<Rgba>]) -> image::RgbaImage
+{
+    let mut out = image::RgbaImage::new((width*TILESIZE) as u32, (height*TILESIZE) as u32);
+    for y in 0..height
+    {
+        for x in 0..width
+        {
+            if let TileType::Tile(id) = types[y*width + x]
+            {
+                for ty in 0..TILESIZE
+                {
+                    for tx in 0..TILESIZE
+                    {
+                        let px = &id_to_tile[id][ty*TILESIZE + tx];
+                        out.put_pixel((x*TILESIZE + tx) as u32, (y*TILESIZE + ty) as u32, image::Rgba([px.r, px.g, px.b, px.a]));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+// one row of TileIds per line, no header
+pub fn grid_csv(types : &[TileType], width : usize, height : usize) -> String
+{
+    let mut out = String::new();
+    for y in 0..height
+    {
+        let row = types[y*width..(y+1)*width].iter().map(|t| cell_index(*t).to_string()).collect::<Vec<_>>().join(",");
+        writeln!(out, "{}", row).unwrap();
+    }
+    out
+}
+
+// reads grid_csv's output back; DEAD_CELL comes back as dead
+pub fn parse_grid_csv(text : &str) -> (Vec<TileType>, usize, usize)
+{
+    let mut types = Vec::new();
+    let mut width = 0;
+    let mut height = 0;
+    for (y, line) in text.lines().filter(|line| !line.trim().is_empty()).enumerate()
+    {
+        let row = line.split(',').map(|cell|
+        {
+            match cell.trim().parse::<i64>().unwrap_or_else(|_| panic!("bad cell {:?} on line {} of the grid", cell, y + 1))
+            {
+                DEAD_CELL => TileType::Dead,
+                id if id >= 0 => TileType::Tile(id as usize),
+                id => panic!("bad tile {} on line {} of the grid", id, y + 1),
+            }
+        }).collect::<Vec<_>>();
+        assert!(y == 0 || row.len() == width, "line {} of the grid is {} cells long instead of {}", y + 1, row.len(), width);
+        width = row.len();
+        height += 1;
+        types.extend(row);
+    }
+    (types, width, height)
+}
+
+pub fn write_grid_csv(path : &str, types : &[TileType], width : usize, height : usize)
+{
+    std::fs::write(path, grid_csv(types, width, height)).unwrap();
+}
+
+pub fn write_grid_json(path : &str, types : &[TileType], width : usize, height : usize, atlas_path : &str, tile_count : usize)
+{
+    let mut out = String::new();
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "  \"width\": {},", width).unwrap();
+    writeln!(out, "  \"height\": {},", height).unwrap();
+    writeln!(out, "  \"tile_size\": {},", TILESIZE).unwrap();
+    writeln!(out, "  \"tile_count\": {},", tile_count).unwrap();
+    writeln!(out, "  \"atlas\": \"{}\",", json::escape(file_name(atlas_path))).unwrap();
+    writeln!(out, "  \"atlas_columns\": {},", atlas_columns(tile_count)).unwrap();
+    writeln!(out, "  \"dead\": {},", DEAD_CELL).unwrap();
+    writeln!(out, "  \"tiles\": [").unwrap();
+    for y in 0..height
+    {
+        let row = types[y*width..(y+1)*width].iter().map(|t| cell_index(*t).to_string()).collect::<Vec<_>>().join(", ");
+        writeln!(out, "    [{}]{}", row, if y+1 < height { "," } else { "" }).unwrap();
+    }
+    writeln!(out, "  ]").unwrap();
+    writeln!(out, "}}").unwrap();
+    std::fs::write(path, out).unwrap();
+}
@@ -0,0 +1,121 @@
+use crate::{load_px_map, Collapser, TileId, TileType, DIRECTIONS};
+
+// how many cells to grow a map by on each side
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+pub struct Margins
+{
+    pub left : usize,
+    pub top : usize,
+    pub right : usize,
+    pub bottom : usize,
+}
+
+impl Margins
+{
+    // either N for every side or LEFT,TOP,RIGHT,BOTTOM
+    pub fn parse(text : &str) -> Margins
+    {
+        let parts = text.split(',').map(|part| part.trim().parse::<usize>().unwrap_or_else(|_| panic!("bad margin {:?} in {}", part, text))).collect::<Vec<_>>();
+        match parts.as_slice()
+        {
+            [n] => Margins { left : *n, top : *n, right : *n, bottom : *n },
+            [left, top, right, bottom] => Margins { left : *left, top : *top, right : *right, bottom : *bottom },
+            _ => panic!("margins have to be N or LEFT,TOP,RIGHT,BOTTOM, got {}", text)
+        }
+    }
+}
+
+// which cells of a width by height map to reroll: the ones in the rectangle, clipped to the map
+pub fn rect_mask(width : usize, height : usize, (x, y, w, h) : (usize, usize, usize, usize)) -> Vec<bool>
+{
+    let mut mask = vec!(false; width*height);
+    for cy in y..std::cmp::min(y + h, height)
+    {
+        for cx in x..std::cmp::min(x + w, width)
+        {
+            mask[cy*width + cx] = true;
+        }
+    }
+    mask
+}
+
+// the same from an image with a pixel per cell, where every pixel that isn't black or transparent marks a cell
+pub fn load_mask(path : &str, width : usize, height : usize) -> Vec<bool>
+{
+    let px_map = load_px_map(std::path::Path::new(path));
+    assert!(px_map.len() == height && px_map[0].len() == width, "the mask has to be {}x{} pixels, one per cell", width, height);
+    px_map.iter().flatten().map(|px| px.a != 0 && (px.r, px.g, px.b) != (0, 0, 0)).collect()
+}
+
+impl Collapser
+{
+    // sets up an output that's an earlier one (border ring included, the way the grid export writes it) grown by the
+    // margins, with its decided cells pinned where they end up so only the rest gets collapsed. the old ring is dropped:
+    // where the map grows its edge gets new neighbors, and where it doesn't it's next to the new ring just like before.
+    // dead cells get another go. the old map has to come from the same example, or its tile ids mean different tiles
+    pub fn extend_from(&mut self, old : &[TileType], old_width : usize, old_height : usize, margins : Margins)
+    {
+        assert!(old_width >= 3 && old_height >= 3, "the map to extend has to be at least 3x3, border ring included");
+        self.set_output_size(old_width + margins.left + margins.right, old_height + margins.top + margins.bottom);
+        for y in 1..old_height - 1
+        {
+            for x in 1..old_width - 1
+            {
+                if let TileType::Tile(id) = old[y*old_width + x]
+                {
+                    assert!(id < self.max_index, "the map to extend has tile {} at {},{}, but the example only has {} tiles", id, x, y, self.max_index);
+                    self.pin_tile(((x + margins.left) as isize, (y + margins.top) as isize), id);
+                }
+            }
+        }
+        self.finish_pinning();
+    }
+    // sets up an output that's an earlier one (the same way) with everything outside the mask pinned as it was, dead
+    // cells included, so that running it only makes the masked cells again, fitting what's around them. the border
+    // ring stays the border ring even if it's masked
+    pub fn reroll_from(&mut self, old : &[TileType], width : usize, height : usize, mask : &[bool])
+    {
+        assert!(mask.len() == width*height, "the mask has to cover the whole map");
+        self.set_output_size(width, height);
+        for y in 1..height - 1
+        {
+            for x in 1..width - 1
+            {
+                let (cell, coord) = (y*width + x, (x as isize, y as isize));
+                match old[cell]
+                {
+                    _ if mask[cell] => {}
+                    TileType::Tile(id) =>
+                    {
+                        assert!(id < self.max_index, "the map to reroll has tile {} at {},{}, but the example only has {} tiles", id, x, y, self.max_index);
+                        self.pin_tile(coord, id);
+                    }
+                    TileType::Dead => *self.get_type_mut(coord.0, coord.1) = TileType::Dead,
+                    TileType::Field => {}
+                }
+            }
+        }
+        // masked cells walled in by dead ones would never be picked otherwise
+        for y in 1..height as isize - 1
+        {
+            for x in 1..width as isize - 1
+            {
+                if self.get_type(x, y) == TileType::Field && DIRECTIONS.iter().any(|dir| self.get_type(x + dir.0, y + dir.1) == TileType::Dead)
+                {
+                    self.candidates.push((x, y));
+                }
+            }
+        }
+        self.finish_pinning();
+    }
+    fn pin_tile(&mut self, (x, y) : (isize, isize), id : TileId)
+    {
+        *self.get_type_mut(x, y) = TileType::Tile(id);
+        for dir in DIRECTIONS.iter()
+        {
+            self.damage.push((x + dir.0, y + dir.1));
+        }
+    }
+}
@@ -0,0 +1,149 @@
+// the cells waiting to be collapsed, in the order they were added. picking the n-th one and taking it out is O(log n)
+// and checking whether a cell is in is O(1), where a Vec's remove and contains are O(n). the order is kept exactly as
+// a Vec would keep it so the same seed still picks the same cells
+#[derive(Clone)]
+pub struct Frontier
+{
+    width : usize,
+    height : usize,
+    // which slot each cell is in, NOT_IN if it isn't
+    slot_of : Vec<usize>,
+    // the cell in each slot, None once it's taken out; slots are handed out in order and only reused by compacting
+    slots : Vec<Option<(isize, isize)>>,
+    // fenwick tree counting the occupied slots, over a power of two so that finding the n-th one is a plain descent
+    tree : Vec<usize>,
+    len : usize,
+}
+
+const NOT_IN : usize = usize::MAX;
+
+impl Frontier
+{
+    pub fn new(width : usize, height : usize) -> Frontier
+    {
+        // a cell can only be in once, so between compactions there's always room for every cell to be added again
+        let capacity = (2*width*height + 16).next_power_of_two();
+        Frontier { width, height, slot_of : vec!(NOT_IN; width*height), slots : Vec::new(), tree : vec!(0; capacity + 1), len : 0 }
+    }
+    fn cell(&self, (x, y) : (isize, isize)) -> usize
+    {
+        let x = x.wrapping_rem_euclid(self.width as isize) as usize;
+        let y = y.wrapping_rem_euclid(self.height as isize) as usize;
+        y*self.width + x
+    }
+    fn capacity(&self) -> usize
+    {
+        self.tree.len() - 1
+    }
+    fn add(&mut self, slot : usize, amount : isize)
+    {
+        let mut i = slot + 1;
+        while i < self.tree.len()
+        {
+            self.tree[i] = (self.tree[i] as isize + amount) as usize;
+            i += i & i.wrapping_neg();
+        }
+    }
+    // the slot of the n-th (from 0) occupied slot
+    fn find(&self, n : usize) -> usize
+    {
+        let mut pos = 0;
+        let mut rest = n;
+        let mut step = self.capacity();
+        while step > 0
+        {
+            if pos + step < self.tree.len() && self.tree[pos + step] <= rest
+            {
+                pos += step;
+                rest -= self.tree[pos];
+            }
+            step /= 2;
+        }
+        pos
+    }
+    // moves everything still in to the front, keeping its order
+    fn compact(&mut self)
+    {
+        let cells = self.slots.drain(..).flatten().collect::<Vec<_>>();
+        self.tree.fill(0);
+        self.len = 0;
+        for cell in cells
+        {
+            let index = self.cell(cell);
+            self.slot_of[index] = NOT_IN;
+            self.push(cell);
+        }
+    }
+    pub fn len(&self) -> usize
+    {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool
+    {
+        self.len == 0
+    }
+    pub fn contains(&self, cell : (isize, isize)) -> bool
+    {
+        self.slot_of[self.cell(cell)] != NOT_IN
+    }
+    // everything still in, in order; pushing these into a new frontier gives one that picks the same cells
+    pub fn cells(&self) -> Vec<(isize, isize)>
+    {
+        self.slots.iter().flatten().copied().collect()
+    }
+    // adds a cell at the end; does nothing if it's already in
+    pub fn push(&mut self, cell : (isize, isize))
+    {
+        let index = self.cell(cell);
+        if self.slot_of[index] != NOT_IN
+        {
+            return;
+        }
+        if self.slots.len() == self.capacity()
+        {
+            self.compact();
+        }
+        let slot = self.slots.len();
+        self.slots.push(Some(cell));
+        self.slot_of[index] = slot;
+        self.add(slot, 1);
+        self.len += 1;
+    }
+    // takes out the n-th cell (from 0) in the order they were added
+    pub fn remove(&mut self, n : usize) -> (isize, isize)
+    {
+        assert!(n < self.len, "frontier index {} out of range for length {}", n, self.len);
+        let slot = self.find(n);
+        self.take(slot)
+    }
+    fn take(&mut self, slot : usize) -> (isize, isize)
+    {
+        let cell = self.slots[slot].take().unwrap();
+        let index = self.cell(cell);
+        self.slot_of[index] = NOT_IN;
+        self.add(slot, -1);
+        self.len -= 1;
+        cell
+    }
+    // a point to truncate back to; everything added after it can be dropped again, as long as nothing gets removed
+    // in between
+    pub fn mark(&mut self) -> usize
+    {
+        if self.capacity() - self.slots.len() < self.width*self.height
+        {
+            self.compact();
+        }
+        self.slots.len()
+    }
+    pub fn truncate(&mut self, mark : usize)
+    {
+        for slot in mark..self.slots.len()
+        {
+            if self.slots[slot].is_some()
+            {
+                self.take(slot);
+            }
+        }
+        self.slots.truncate(mark);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{Collapser, TileType};
+
+// the old fields (and type) of every cell changed since a collapse started, so that a failed one can be rolled back for the cost
+// of what it touched instead of copying every cell's fields up front just in case
+#[derive(Clone)]
+#[derive(Default)]
+pub struct Journal
+{
+    active : bool,
+    // (cell, where its old fields start in values), in the order they were first touched
+    cells : Vec<(usize, usize)>,
+    types : Vec<TileType>,
+    values : Vec<f64>,
+    touched : Vec<bool>,
+}
+
+impl Journal
+{
+    pub fn new(cell_count : usize) -> Journal
+    {
+        Journal { touched : vec!(false; cell_count), ..Default::default() }
+    }
+    // to be called with a cell's type and fields right before either gets changed
+    #[inline(always)]
+    pub fn record(&mut self, cell : usize, tile_type : TileType, fields : &[f64])
+    {
+        if self.active && !self.touched[cell]
+        {
+            self.touched[cell] = true;
+            self.cells.push((cell, self.values.len()));
+            self.types.push(tile_type);
+            self.values.extend_from_slice(fields);
+        }
+    }
+    // (cell, old type, old fields) of everything recorded so far
+    pub fn entries(&self, stride : usize) -> impl Iterator<Item = (usize, TileType, &[f64])>
+    {
+        self.cells.iter().zip(self.types.iter()).map(move |((cell, start), tile_type)| (*cell, *tile_type, &self.values[*start..start + stride]))
+    }
+    fn clear(&mut self)
+    {
+        for (cell, _) in self.cells.drain(..)
+        {
+            self.touched[cell] = false;
+        }
+        self.types.clear();
+        self.values.clear();
+        self.active = false;
+    }
+}
+
+impl Collapser
+{
+    pub(crate) fn begin_journal(&mut self)
+    {
+        assert!(!self.journal.active, "journals don't nest");
+        self.journal.active = true;
+    }
+    // records a cell whose type is about to change without its fields being touched
+    pub(crate) fn journal_cell(&mut self, (x, y) : (isize, isize))
+    {
+        self.get_all_fields_mut(x, y);
+    }
+    // keeps every change made since begin_journal
+    pub(crate) fn end_journal(&mut self)
+    {
+        if self.tracks_minimums()
+        {
+            let change = self.journal_possible_change();
+            self.apply_possible_change(&change);
+        }
+        self.journal.clear();
+    }
+    // puts the fields of every cell changed since begin_journal back the way they were; cell types are left alone
+    pub(crate) fn rollback_journal(&mut self)
+    {
+        let stride = crate::DIRECTIONS.len()*self.max_index;
+        for (cell, start) in self.journal.cells.iter()
+        {
+            self.out_map_fields[cell*stride..(cell + 1)*stride].copy_from_slice(&self.journal.values[*start..start + stride]);
+        }
+        self.journal.clear();
+    }
+}
@@ -0,0 +1,268 @@
+// just enough json to read the files we write and tiled's .tmj/.tsj files. it's hand-written like the writers are:
+// those files only need objects, arrays, strings and numbers looked up by key, which isn't worth pulling serde and its
+// derive macros into a crate that otherwise only needs image and oorandom. anything malformed is an Err, never a panic
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Value
+{
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value
+{
+    pub fn get(&self, key : &str) -> Option<&Value>
+    {
+        match self
+        {
+            Value::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None
+        }
+    }
+    pub fn as_f64(&self) -> Option<f64>
+    {
+        match self
+        {
+            Value::Number(n) => Some(*n),
+            _ => None
+        }
+    }
+    pub fn as_u64(&self) -> Option<u64>
+    {
+        self.as_f64().map(|n| n as u64)
+    }
+    pub fn as_usize(&self) -> Option<usize>
+    {
+        self.as_f64().map(|n| n as usize)
+    }
+    pub fn as_str(&self) -> Option<&str>
+    {
+        match self
+        {
+            Value::String(s) => Some(s.as_str()),
+            _ => None
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Value]>
+    {
+        match self
+        {
+            Value::Array(a) => Some(&a[..]),
+            _ => None
+        }
+    }
+}
+
+// deeper than this is an error instead of a stack overflow
+const MAX_DEPTH : usize = 256;
+
+struct Parser<'a>
+{
+    text : &'a [u8],
+    pos : usize,
+    depth : usize,
+}
+
+impl<'a> Parser<'a>
+{
+    fn error(&self, what : &str) -> String
+    {
+        format!("json error at byte {}: {}", self.pos, what)
+    }
+    fn skip_whitespace(&mut self)
+    {
+        while self.pos < self.text.len() && (self.text[self.pos] as char).is_ascii_whitespace()
+        {
+            self.pos += 1;
+        }
+    }
+    fn peek(&mut self) -> Option<u8>
+    {
+        self.skip_whitespace();
+        self.text.get(self.pos).copied()
+    }
+    fn expect(&mut self, c : u8) -> Result<(), String>
+    {
+        if self.peek() == Some(c)
+        {
+            self.pos += 1;
+            Ok(())
+        }
+        else
+        {
+            Err(self.error(&format!("expected '{}'", c as char)))
+        }
+    }
+    fn literal(&mut self, word : &str, value : Value) -> Result<Value, String>
+    {
+        if self.text[self.pos..].starts_with(word.as_bytes())
+        {
+            self.pos += word.len();
+            Ok(value)
+        }
+        else
+        {
+            Err(self.error("unknown literal"))
+        }
+    }
+    fn value(&mut self) -> Result<Value, String>
+    {
+        if self.depth >= MAX_DEPTH
+        {
+            return Err(self.error("nested too deeply"));
+        }
+        self.depth += 1;
+        let value = self.nested_value();
+        self.depth -= 1;
+        value
+    }
+    fn nested_value(&mut self) -> Result<Value, String>
+    {
+        match self.peek()
+        {
+            Some(b'{') =>
+            {
+                self.pos += 1;
+                let mut members = Vec::new();
+                if self.peek() == Some(b'}')
+                {
+                    self.pos += 1;
+                    return Ok(Value::Object(members));
+                }
+                loop
+                {
+                    let key = self.string()?;
+                    self.expect(b':')?;
+                    members.push((key, self.value()?));
+                    match self.peek()
+                    {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => { self.pos += 1; return Ok(Value::Object(members)); }
+                        _ => return Err(self.error("expected ',' or '}'"))
+                    }
+                }
+            }
+            Some(b'[') =>
+            {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.peek() == Some(b']')
+                {
+                    self.pos += 1;
+                    return Ok(Value::Array(items));
+                }
+                loop
+                {
+                    items.push(self.value()?);
+                    match self.peek()
+                    {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => { self.pos += 1; return Ok(Value::Array(items)); }
+                        _ => return Err(self.error("expected ',' or ']'"))
+                    }
+                }
+            }
+            Some(b'"') => Ok(Value::String(self.string()?)),
+            Some(b't') => self.literal("true", Value::Bool(true)),
+            Some(b'f') => self.literal("false", Value::Bool(false)),
+            Some(b'n') => self.literal("null", Value::Null),
+            Some(_) => self.number(),
+            None => Err(self.error("unexpected end of input"))
+        }
+    }
+    fn number(&mut self) -> Result<Value, String>
+    {
+        let start = self.pos;
+        while self.pos < self.text.len() && b"+-0123456789.eE".contains(&self.text[self.pos])
+        {
+            self.pos += 1;
+        }
+        let s = std::str::from_utf8(&self.text[start..self.pos]).unwrap();
+        s.parse().map(Value::Number).map_err(|_| self.error("bad number"))
+    }
+    fn hex4(&mut self) -> Result<u32, String>
+    {
+        let hex = self.text.get(self.pos..self.pos + 4).filter(|hex| hex.iter().all(u8::is_ascii_hexdigit)).ok_or_else(|| self.error("bad escape"))?;
+        self.pos += 4;
+        Ok(hex.iter().fold(0, |code, digit| code*16 + (*digit as char).to_digit(16).unwrap()))
+    }
+    fn string(&mut self) -> Result<String, String>
+    {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop
+        {
+            let c = *self.text.get(self.pos).ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match c
+            {
+                b'"' => break,
+                b'\\' =>
+                {
+                    let c = *self.text.get(self.pos).ok_or_else(|| self.error("unterminated string"))?;
+                    self.pos += 1;
+                    match c
+                    {
+                        b'n' => out.push(b'\n'),
+                        b't' => out.push(b'\t'),
+                        b'r' => out.push(b'\r'),
+                        b'b' => out.push(8),
+                        b'f' => out.push(12),
+                        b'u' =>
+                        {
+                            let mut code = self.hex4()?;
+                            // a character outside the basic plane is written as a pair of surrogates
+                            if (0xd800..0xdc00).contains(&code) && self.text[self.pos..].starts_with(b"\\u")
+                            {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = if (0xdc00..0xe000).contains(&low) { 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00) } else { 0xfffd };
+                            }
+                            let mut buf = [0; 4];
+                            out.extend_from_slice(char::from_u32(code).unwrap_or('\u{FFFD}').encode_utf8(&mut buf).as_bytes());
+                        }
+                        b'"' | b'\\' | b'/' => out.push(c),
+                        _ => return Err(self.error("bad escape"))
+                    }
+                }
+                other => out.push(other)
+            }
+        }
+        String::from_utf8(out).map_err(|_| self.error("invalid utf-8"))
+    }
+}
+
+pub fn parse(text : &str) -> Result<Value, String>
+{
+    let mut parser = Parser { text : text.as_bytes(), pos : 0, depth : 0 };
+    let value = parser.value()?;
+    if parser.peek().is_some()
+    {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(value)
+}
+
+// escapes a string for writing it between double quotes
+pub fn escape(s : &str) -> String
+{
+    let mut out = String::new();
+    for c in s.chars()
+    {
+        match c
+        {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out
+}
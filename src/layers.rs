@@ -0,0 +1,117 @@
+use crate::{Rgba, RgbaF, TileId, TileType, TILESIZE};
+use std::collections::BTreeMap;
+
+// a layer of an example map: its name, its map of tile ids, and what each id looks like
+pub type LayerInput = (String, Vec<Vec<TileId>>, Vec<Vec<Rgba>>);
+
+// one tile layer of a (possibly) multi-layer map
+pub struct Layer
+{
+    pub name : String,
+    pub id_to_tile : Vec<Vec<Rgba>>,
+}
+
+// the solver works on whole stacks of tiles (one per layer) as if they were single tiles, and collapsing a cell
+// collapses all of its layers at once. that's all the cross-layer constraint there is: a decoration can only sit on the
+// ground tiles it sits on somewhere in the example, and adjacency is learned between whole stacks, so a decoration
+// next to some ground tile only shows up if that exact pair of stacks is next to each other in the example. layers
+// aren't learned on their own, so the more layers and the more ways they combine, the more example it takes
+pub struct Layers
+{
+    pub layers : Vec<Layer>,
+    // stacks[stack id][layer index] = the tile id within that layer
+    pub stacks : Vec<Vec<TileId>>,
+}
+
+impl Layers
+{
+    pub fn single(name : String, id_to_tile : &[Vec<Rgba>]) -> Layers
+    {
+        Layers
+        {
+            layers : vec!(Layer { name, id_to_tile : id_to_tile.to_vec() }),
+            stacks : (0..id_to_tile.len()).map(|id| vec!(id)).collect(),
+        }
+    }
+    // combines same-sized layer maps (bottom layer first) into one map of stack ids,
+    // returning it along with the flattened look of each stack
+    pub fn stack(inputs : Vec<LayerInput>) -> (Layers, Vec<Vec<TileId>>, Vec<Vec<Rgba>>)
+    {
+        let height = inputs[0].1.len();
+        let width = inputs[0].1[0].len();
+        for (name, map, _) in &inputs
+        {
+            assert!(map.len() == height && map[0].len() == width, "layer {} is {}x{} tiles, expected {}x{}", name, map[0].len(), map.len(), width, height);
+        }
+        let mut stack_to_id = BTreeMap::new();
+        let mut stacks = Vec::new();
+        let mut map = Vec::with_capacity(height);
+        for y in 0..height
+        {
+            let mut row = Vec::with_capacity(width);
+            for x in 0..width
+            {
+                let stack = inputs.iter().map(|(_, layer_map, _)| layer_map[y][x]).collect::<Vec<_>>();
+                let id = *stack_to_id.entry(stack.clone()).or_insert_with(|| { stacks.push(stack); stacks.len() - 1 });
+                row.push(id);
+            }
+            map.push(row);
+        }
+        let layers = Layers { layers : inputs.into_iter().map(|(name, _, id_to_tile)| Layer { name, id_to_tile }).collect(), stacks };
+        let id_to_tile = (0..layers.stacks.len()).map(|id| layers.flatten(id)).collect();
+        (layers, map, id_to_tile)
+    }
+    pub fn len(&self) -> usize
+    {
+        self.layers.len()
+    }
+    // alpha-composites a stack bottom to top
+    fn flatten(&self, stack : TileId) -> Vec<Rgba>
+    {
+        let mut out = vec!(RgbaF::new(0.0, 0.0, 0.0, 0.0); TILESIZE*TILESIZE);
+        for (layer, id) in self.layers.iter().zip(self.stacks[stack].iter())
+        {
+            for (dst, src) in out.iter_mut().zip(layer.id_to_tile[*id].iter())
+            {
+                let src = src.to_float();
+                let a = src.a + dst.a*(1.0 - src.a);
+                if a > 0.0
+                {
+                    dst.r = (src.r*src.a + dst.r*dst.a*(1.0 - src.a))/a;
+                    dst.g = (src.g*src.a + dst.g*dst.a*(1.0 - src.a))/a;
+                    dst.b = (src.b*src.a + dst.b*dst.a*(1.0 - src.a))/a;
+                }
+                dst.a = a;
+            }
+        }
+        out.iter().map(|px| px.to_u8()).collect()
+    }
+    // the cells of a finished map as seen on a single layer
+    pub fn layer_types(&self, layer : usize, types : &[TileType]) -> Vec<TileType>
+    {
+        types.iter().map(|t| match t
+        {
+            TileType::Tile(id) => TileType::Tile(self.stacks[*id][layer]),
+            other => *other
+        }).collect()
+    }
+    // something safe to put in a file name for each layer
+    pub fn file_tag(&self, layer : usize) -> String
+    {
+        let name = self.layers[layer].name.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect::<String>();
+        if name.is_empty()
+        {
+            format!("layer{}", layer)
+        }
+        else
+        {
+            format!("{}-{}", layer, name)
+        }
+    }
+}
+
+// fully transparent tiles are what empty cells of upper layers look like
+pub fn is_empty_tile(tile : &[Rgba]) -> bool
+{
+    tile.iter().all(|px| px.a == 0)
+}
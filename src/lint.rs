@@ -0,0 +1,226 @@
+use crate::{get_opposite_direction, rules::direction_name, Collapser, TileId, DIRECTIONS};
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+// problems with the learned model that can be found before generating anything
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Issue
+{
+    // the tile isn't anywhere in the example (a tileset tile the map never uses), so nothing was learned about it
+    Unused(TileId),
+    // nothing may sit in that direction of the tile, so it can never be placed
+    NoNeighbor(TileId, usize),
+    // the first tile may sit in that direction of the second, but not the other way around
+    Mismatch(TileId, TileId, usize),
+    // nothing connects the tile to the ones next to the border, where generation starts, so it's never placed
+    Unreachable(TileId),
+    // the tile with first in one direction and second in another leaves nothing for the cell diagonal from it,
+    // between the two
+    DeadEnd { tile : TileId, first : (TileId, usize), second : (TileId, usize) },
+}
+
+impl Issue
+{
+    pub fn describe(&self) -> String
+    {
+        match self
+        {
+            Issue::Unused(tile) => format!("tile {} isn't in the example", tile),
+            Issue::NoNeighbor(tile, dir) => format!("nothing may go {} of tile {}", direction_name(*dir), tile),
+            Issue::Mismatch(a, b, dir) => format!("tile {} may go {} of tile {}, but not the other way around", a, direction_name(*dir), b),
+            Issue::Unreachable(tile) => format!("tile {} can't be reached from the border by going from neighbor to neighbor", tile),
+            Issue::DeadEnd { tile, first, second } => format!("tile {} with tile {} {} of it and tile {} {} of it leaves nothing for the corner between them",
+                tile, first.0, direction_name(first.1), second.0, direction_name(second.1)),
+        }
+    }
+    fn kind(&self) -> &'static str
+    {
+        match self
+        {
+            Issue::Unused(_) => "unused",
+            Issue::NoNeighbor(..) => "no_neighbor",
+            Issue::Mismatch(..) => "mismatch",
+            Issue::Unreachable(_) => "unreachable",
+            Issue::DeadEnd { .. } => "dead_end",
+        }
+    }
+}
+
+// tilesets up to this many tiles are linted before every run; bigger ones only with --lint
+pub const AUTO_LINT_MAX_TILES : usize = 64;
+
+// how many issues of each kind get printed before the rest are only counted
+const PRINTED_PER_KIND : usize = 5;
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Lint
+{
+    pub issues : Vec<Issue>,
+}
+
+impl Lint
+{
+    pub fn is_clean(&self) -> bool
+    {
+        self.issues.is_empty()
+    }
+    pub fn print_summary(&self)
+    {
+        for kind in ["unused", "no_neighbor", "mismatch", "unreachable", "dead_end"]
+        {
+            let issues = self.issues.iter().filter(|issue| issue.kind() == kind).collect::<Vec<_>>();
+            for issue in issues.iter().take(PRINTED_PER_KIND)
+            {
+                println!("!!!!---- tileset: {}", issue.describe());
+            }
+            if issues.len() > PRINTED_PER_KIND
+            {
+                println!("!!!!---- tileset: ...and {} more like that", issues.len() - PRINTED_PER_KIND);
+            }
+        }
+    }
+    pub fn to_json(&self) -> String
+    {
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"clean\": {},", self.is_clean()).unwrap();
+        writeln!(out, "  \"issues\": [").unwrap();
+        for (i, issue) in self.issues.iter().enumerate()
+        {
+            let fields = match issue
+            {
+                Issue::Unused(tile) | Issue::Unreachable(tile) => format!("\"tile\": {}", tile),
+                Issue::NoNeighbor(tile, dir) => format!("\"tile\": {}, \"direction\": \"{}\"", tile, direction_name(*dir)),
+                Issue::Mismatch(a, b, dir) => format!("\"tile\": {}, \"other\": {}, \"direction\": \"{}\"", a, b, direction_name(*dir)),
+                Issue::DeadEnd { tile, first, second } => format!("\"tile\": {}, \"first\": {}, \"first_direction\": \"{}\", \"second\": {}, \"second_direction\": \"{}\"",
+                    tile, first.0, direction_name(first.1), second.0, direction_name(second.1)),
+            };
+            writeln!(out, "    {{\"kind\": \"{}\", {}}}{}", issue.kind(), fields, if i + 1 < self.issues.len() { "," } else { "" }).unwrap();
+        }
+        writeln!(out, "  ]").unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl Collapser
+{
+    // the tiles that may sit in direction dir of each tile, a bit each, leaving out forbidden ones
+    fn allowed_sets(&self, words : usize) -> Vec<u64>
+    {
+        let mut sets = vec!(0; self.max_index*DIRECTIONS.len()*words);
+        for a in 0..self.max_index
+        {
+            for dir in 0..DIRECTIONS.len()
+            {
+                for b in (0..self.max_index).filter(|b| !self.forbidden_tiles.contains(b) && self.edge_weight(a, *b, dir) != 0.0)
+                {
+                    sets[(a*DIRECTIONS.len() + dir)*words + b/64] |= 1 << (b%64);
+                }
+            }
+        }
+        sets
+    }
+    // checks the learned model for tiles that can never be placed and small configurations that can't be finished.
+    // forbidden tiles (the border) are only checked for mismatches
+    pub fn lint(&self) -> Lint
+    {
+        let mut lint = Lint::default();
+        let placeable = |id : TileId| !self.forbidden_tiles.contains(&id) && self.freqs[id] > 0.0;
+        for a in (0..self.max_index).filter(|a| !self.forbidden_tiles.contains(a) && self.freqs[*a] == 0.0)
+        {
+            lint.issues.push(Issue::Unused(a));
+        }
+        for a in (0..self.max_index).filter(|a| placeable(*a))
+        {
+            for dir in (0..DIRECTIONS.len()).filter(|dir| (0..self.max_index).all(|b| self.edge_weight(a, b, *dir) == 0.0))
+            {
+                lint.issues.push(Issue::NoNeighbor(a, dir));
+            }
+        }
+        for a in 0..self.max_index
+        {
+            for b in 0..self.max_index
+            {
+                for dir in 0..DIRECTIONS.len()
+                {
+                    if self.edge_weight(b, a, dir) != 0.0 && self.edge_weight(a, b, get_opposite_direction(dir)) == 0.0
+                    {
+                        lint.issues.push(Issue::Mismatch(a, b, dir));
+                    }
+                }
+            }
+        }
+
+        // generation spreads out from the border, one neighbor at a time
+        let mut reached = vec!(false; self.max_index);
+        let mut queue = VecDeque::new();
+        for a in (0..self.max_index).filter(|a| placeable(*a))
+        {
+            if self.forbidden_tiles.iter().any(|f| (0..DIRECTIONS.len()).any(|dir| self.edge_weight(*f, a, dir) != 0.0))
+            {
+                reached[a] = true;
+                queue.push_back(a);
+            }
+        }
+        while let Some(a) = queue.pop_front()
+        {
+            for (b, reached) in reached.iter_mut().enumerate()
+            {
+                if placeable(b) && !*reached && (0..DIRECTIONS.len()).any(|dir| self.edge_weight(a, b, dir) != 0.0)
+                {
+                    *reached = true;
+                    queue.push_back(b);
+                }
+            }
+        }
+        for a in (0..self.max_index).filter(|a| placeable(*a) && !reached[*a])
+        {
+            lint.issues.push(Issue::Unreachable(a));
+        }
+
+        // an L of three cells that the rules allow, whose fourth corner nothing fits
+        let words = self.max_index.div_ceil(64);
+        let sets = self.allowed_sets(words);
+        let set = |a : TileId, dir : usize| &sets[(a*DIRECTIONS.len() + dir)*words..(a*DIRECTIONS.len() + dir + 1)*words];
+        let tiles = |bits : &[u64]| (0..bits.len()*64).filter(|id| bits[id/64] & (1 << (id%64)) != 0).collect::<Vec<_>>();
+        for a in (0..self.max_index).filter(|a| placeable(*a))
+        {
+            for (d1, offset1) in DIRECTIONS.iter().enumerate()
+            {
+                for (d2, offset2) in DIRECTIONS.iter().enumerate().skip(d1 + 1)
+                {
+                    let corner = (offset1.0 + offset2.0, offset1.1 + offset2.1);
+                    if corner.0.abs() != 1 || corner.1.abs() != 1
+                    {
+                        continue;
+                    }
+                    for b in tiles(set(a, d1))
+                    {
+                        for c in tiles(set(a, d2))
+                        {
+                            // the corner is in direction d2 of b and in direction d1 of c
+                            if set(b, d2).iter().zip(set(c, d1).iter()).all(|(x, y)| x & y == 0)
+                            {
+                                lint.issues.push(Issue::DeadEnd { tile : a, first : (b, d1), second : (c, d2) });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        lint
+    }
+    // prints it and writes {stem}_lint.json
+    pub fn write_lint(&self, stem : &str) -> Lint
+    {
+        let lint = self.lint();
+        lint.print_summary();
+        std::fs::write(format!("{}_lint.json", stem), lint.to_json()).unwrap();
+        lint
+    }
+}
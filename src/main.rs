@@ -1,10 +1,38 @@
+
 extern crate image;
 
+mod anim;
+mod batch;
+mod bitset;
+mod checkpoint;
+mod connectivity;
+mod constraints;
+mod export;
+mod extend;
+mod frontier;
+mod journal;
+mod json;
+mod layers;
+mod lint;
+mod parallel;
+mod portable;
+mod rng;
+mod rules;
+mod settings;
+mod stats;
+#[cfg(test)]
+mod tests;
+mod tiled;
+mod validate;
+mod world;
+mod xml;
+
 use std::env::args as args;
 use image::io::Reader as ImageReader;
 #[allow(unused_imports)]
 use image::{DynamicImage, GenericImageView, Pixel};
 use std::collections::{BTreeMap};
+use std::sync::Arc;
 
 #[derive(Copy)]
 #[derive(Clone)]
@@ -23,6 +51,7 @@ impl RgbaF {
         RgbaF{r, g, b, a}
     }
     #[inline(always)]
+    #[allow(clippy::wrong_self_convention)]
     fn to_u8(&self) -> Rgba
     {
         Rgba{r : (self.r * 255.0).round() as u8, g : (self.g * 255.0).round() as u8, b : (self.b * 255.0).round() as u8, a : (self.a * 255.0).round() as u8}
@@ -69,6 +98,7 @@ impl Rgba {
         Rgba{r, g, b, a}
     }
     #[inline(always)]
+    #[allow(clippy::wrong_self_convention)]
     fn to_float(&self) -> RgbaF
     {
         RgbaF{r : self.r as f32/255.0, g : self.g as f32/255.0, b : self.b as f32/255.0, a : self.a as f32/255.0}
@@ -96,6 +126,11 @@ fn eq(a : &Vec<Vec<Rgba>>, b : &Vec<Vec<Rgba>>) -> bool
 */
 
 const TILESIZE : usize = 16;
+// tile weights are products of several small field values; scaling them up keeps them well away from
+// the subnormal range, and cancels out when sampling
+const FREQ_MULTIPLIER_SCALE : f64 = 1000000000.0;
+const DEFAULT_FRAME_DELAY_MS : u32 = 100;
+const FINAL_FRAME_DELAY_MS : u32 = 3000;
 const DIRECTIONS : [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
 //const DIRECTIONS : [(isize, isize); 8] = [(1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1), (0, -1), (1, -1)];
 fn get_opposite_direction(dir_index : usize) -> usize
@@ -103,7 +138,7 @@ fn get_opposite_direction(dir_index : usize) -> usize
     (dir_index+DIRECTIONS.len()/2)%DIRECTIONS.len()
 }
 
-fn get_tile_from_image(a : &Vec<Vec<Rgba>>, (x, y) : (usize, usize)) -> Vec<Rgba>
+fn get_tile_from_image(a : &[Vec<Rgba>], (x, y) : (usize, usize)) -> Vec<Rgba>
 {
     let mut ret = vec!(Rgba::new(255, 255, 255, 255); TILESIZE*TILESIZE);
     
@@ -120,6 +155,12 @@ fn get_tile_from_image(a : &Vec<Vec<Rgba>>, (x, y) : (usize, usize)) -> Vec<Rgba
 
 type TileId = usize;
 
+// "out.png" -> "out", for naming the other files that get written next to the output image
+fn output_stem(namebase : &str) -> &str
+{
+    namebase.strip_suffix(".png").unwrap_or(namebase)
+}
+
 
 //#[derive(Clone)]
 //#[derive(Debug)]
@@ -156,726 +197,995 @@ impl TileType
     }
 }
 
-fn main() {
-    let args = args().collect::<Vec<String>>();
-    assert!(args.len() >= 3);
-    let img = ImageReader::open(&args[1]).unwrap().decode().unwrap();
-    let mut px_map = Vec::with_capacity(img.height() as usize);
-    for y in 0..img.height()
-    {
-        let mut row = Vec::with_capacity(img.width() as usize);
-        for x in 0..img.width()
-        {
-            let px : image::Rgba<u8> = img.get_pixel(x, y);
-            row.push(Rgba::new(px[0], px[1], px[2], px[3]));
-        }
-        px_map.push(row);
-    }
+struct Collapser
+{
+    // the learned model; the big parts are shared between forks
+    max_index : TileId,
+    //tile_to_id : BTreeMap<Vec<Rgba>, usize>,
+    id_to_tile : Arc<Vec<Vec<Rgba>>>,
+    //map : Vec<TileId>,
+    // what it was learned from, for comparing outputs against
+    example : Arc<Vec<Vec<TileId>>>,
+    forbidden_tiles : Vec<TileId>,
+    freqs : Arc<Vec<f64>>,
+    total_freq : f64,
+    ships : Arc<Vec<f64>>,
+    most_common : TileId,
+    width : usize,
+    height : usize,
+    // what the sampling weights come from; the bitset solver keeps which tiles are possible separately
+    out_map_fields : Vec<f64>,
+    out_map_types : Vec<TileType>,
+    possibilities : Option<bitset::Possibilities>,
+    // what to roll back if the collapse in progress fails
+    journal : journal::Journal,
+
+    damage : Vec<(isize, isize)>,
+    candidates : frontier::Frontier,
+
+    out_freqs : Vec<f64>,
+    out_total_freq : f64,
     
-    struct Collapser
-    {
-        max_index : TileId,
-        //tile_to_id : BTreeMap<Vec<Rgba>, usize>,
-        id_to_tile : Vec<Vec<Rgba>>,
-        //map : Vec<TileId>,
-        forbidden_tiles : Vec<TileId>,
-        freqs : Vec<f64>,
-        total_freq : f64,
-        ships : Vec<f64>,
-        most_common : TileId,
-        width : usize,
-        height : usize,
-        out_map_fields : Vec<f64>,
-        out_map_types : Vec<TileType>,
+    namebase : String,
+    recorder : Option<anim::Recorder>,
+    // whether to save intermediate and failure snapshots at all, as opposed to just the final map
+    snapshots : bool,
+    layers : Arc<layers::Layers>,
     
-        damage : Vec<(isize, isize)>,
-        candidates : Vec<(isize, isize)>,
+    // every decided cell, pinned ones included
+    tile_counts : Vec<usize>,
+    count_constraints : Vec<constraints::CountConstraint>,
+    // per count constraint, how many undecided cells could still hold its tile, and how many are undecided at all;
+    // only kept up to date while some constraint has a minimum
+    possible_counts : Vec<usize>,
+    undecided : usize,
     
-        out_freqs : Vec<f64>,
-        out_total_freq : f64,
-        
-        namebase : String
-    }
+    // per tile; empty unless the walkable area is required to be connected
+    walkable : Vec<bool>,
+    required_walkable : Vec<(isize, isize)>,
+    // how many cells are walkable or required to be, while they're all in one piece; None when there's nothing to
+    // keep connected or it's already split into walkable_pieces
+    walkable_anchors : Option<usize>,
+    walkable_pieces : usize,
     
-    impl Collapser
+    settings : settings::Settings,
+    // what share of the output each tile should make up; the example's frequencies unless overridden
+    target_freqs : Vec<f64>,
+}
+
+impl Collapser
+{
+    // learns from several stacked layers of the same size, bottom layer first, as one layer of stacks (see Layers)
+    fn from_layers(inputs : Vec<layers::LayerInput>, namebase : String) -> Collapser
+    {
+        if inputs.len() == 1
+        {
+            let (name, map, id_to_tile) = inputs.into_iter().next().unwrap();
+            let mut collapse = Collapser::from_map(map, id_to_tile, namebase);
+            collapse.layers = Arc::new(layers::Layers::single(name, &collapse.id_to_tile));
+            return collapse;
+        }
+        for (name, _, id_to_tile) in &inputs
+        {
+            println!("layer \"{}\" has {} unique tiles", name, id_to_tile.len());
+        }
+        let (layers, map, id_to_tile) = layers::Layers::stack(inputs);
+        println!("the layers make {} distinct stacks, and only those get generated, next to the ones they're next to in the example", layers.stacks.len());
+        let mut collapse = Collapser::from_map(map, id_to_tile, namebase);
+        collapse.layers = Arc::new(layers);
+        collapse
+    }
+    // learns from a map of tile ids directly; id_to_tile[id] is only used for drawing. the map's one layer is named
+    // "collapse" (from_layers names it after the input instead)
+    fn from_map(map : Vec<Vec<TileId>>, id_to_tile : Vec<Vec<Rgba>>, namebase : String) -> Collapser
     {
-        fn init(px_map : &Vec<Vec<Rgba>>, width : usize, height : usize, namebase : String) -> Collapser
+        let max_index = id_to_tile.len();
+        println!("number of unique tiles: {}", max_index);
+        let layers = layers::Layers::single("collapse".to_string(), &id_to_tile);
+        
+        let forbidden_tiles = vec!(map[0][0]);
+        
+        //let mut ships = Vec::with_capacity(max_index);
+        //for _ in 0..max_index
+        //{
+        //    ships.push(vec![default_neighbor(max_index); DIRECTIONS.len()]);
+        //}
+        let mut ships = vec!(0.0; max_index*max_index*DIRECTIONS.len());
+
+        let mut freqs = vec!(0.0; max_index);
+        
+        //fn in_bounds(map : &Vec<Vec<TileId>>, (x, y) : (isize, isize)) -> bool
+        //{
+        //    x >= 0 && x < map[0].len() as isize && y >= 0 && y < map.len() as isize
+        //}
+        
+        #[allow(unused_assignments)]
+        let mut most_common = 0;
+        let mut most_common_freq = 0.0;
+        for y in 0..map.len()
         {
-            let mut max_index = 0;
-            let mut tile_to_id = BTreeMap::new();
-            let mut id_to_tile = Vec::new();
-            let mut map = Vec::with_capacity(height as usize/TILESIZE);
-            for y in 0..height/TILESIZE
+            for x in 0..map[0].len()
             {
-                let mut row = Vec::with_capacity(width as usize/TILESIZE);
-                for x in 0..width/TILESIZE
+                freqs[map[y][x]] += 1.0;
+                let id = map[y][x];
+                let freq = freqs[map[y][x]];
+                if freq > most_common_freq
                 {
-                    let tile = get_tile_from_image(&px_map, (x, y));
-                    let id = tile_to_id.entry(tile.clone()).or_insert_with(|| {max_index += 1; max_index - 1});
-                    if *id >= id_to_tile.len()
-                    {
-                        id_to_tile.push(tile);
-                    }
-                    
-                    row.push(*id);
-                    //print!("{} ", *id);
+                    most_common_freq = freq;
+                    most_common = id;
                 }
-                //println!();
-                map.push(row);
-            }
-            println!("number of unique tiles: {}", max_index);
-            
-            let mut forbidden_tiles = Vec::new();
-            forbidden_tiles.push(map[0][0]);
-            
-            //let mut ships = Vec::with_capacity(max_index);
-            //for _ in 0..max_index
-            //{
-            //    ships.push(vec![default_neighbor(max_index); DIRECTIONS.len()]);
-            //}
-            let mut ships = vec!(0.0; max_index*max_index*DIRECTIONS.len());
-    
-            let mut freqs = vec!(0.0; max_index);
-            
-            //fn in_bounds(map : &Vec<Vec<TileId>>, (x, y) : (isize, isize)) -> bool
-            //{
-            //    x >= 0 && x < map[0].len() as isize && y >= 0 && y < map.len() as isize
-            //}
-            
-            #[allow(unused_assignments)]
-            let mut most_common = 0;
-            let mut most_common_freq = 0.0;
-            for y in 0..map.len()
-            {
-                for x in 0..map[0].len()
+                for (i, offset) in DIRECTIONS.iter().enumerate()
                 {
-                    freqs[map[y][x]] += 1.0;
-                    let id = map[y][x];
-                    let freq = freqs[map[y][x]];
-                    if freq > most_common_freq
-                    {
-                        most_common_freq = freq;
-                        most_common = id;
-                    }
-                    for (i, offset) in DIRECTIONS.iter().enumerate()
+                    let offset = (offset.0 + x as isize, offset.1 + y as isize);
+                    //if in_bounds(&map, offset)
                     {
-                        let offset = (offset.0 + x as isize, offset.1 + y as isize);
-                        //if in_bounds(&map, offset)
+                        // wrapping
+                        let value = map[offset.1.wrapping_rem_euclid(map.len() as isize) as usize][offset.0.wrapping_rem_euclid(map[0].len() as isize) as usize];
+                        // non-wrapping
+                        //value = map[offset.1 as usize][offset.0 as usize];
+                        //ships[id][i][value] += 1.0;
+                        ships[id*max_index*DIRECTIONS.len() + value*DIRECTIONS.len() + i] += 1.0;
+                        if id == 1 && value == 0 && i == 1
                         {
-                            // wrapping
-                            let value = map[offset.1.wrapping_rem_euclid(map.len() as isize) as usize][offset.0.wrapping_rem_euclid(map[0].len() as isize) as usize];
-                            // non-wrapping
-                            //value = map[offset.1 as usize][offset.0 as usize];
-                            //ships[id][i][value] += 1.0;
-                            ships[id*max_index*DIRECTIONS.len() + value*DIRECTIONS.len() + i] += 1.0;
-                            if id == 1 && value == 0 && i == 1
-                            {
-                                println!("added entry for {}->{} in direction {} at {},{}", id, value, i, x, y);
-                            }
-                            //println!("added to direction {} for tile type {}", i, id);
-                            //ships[id][i][value] = 1.0;
+                            println!("added entry for {}->{} in direction {} at {},{}", id, value, i, x, y);
                         }
-                        //else
-                        //{
-                        //    //println!("direction {} for tile centered at {},{} is out of bounds", i, x, y);
-                        //}
+                        //println!("added to direction {} for tile type {}", i, id);
+                        //ships[id][i][value] = 1.0;
                     }
+                    //else
+                    //{
+                    //    //println!("direction {} for tile centered at {},{} is out of bounds", i, x, y);
+                    //}
                 }
             }
-            
-            let total_freq : f64 = freqs.iter().sum();
-            
-            for a in 0..max_index
+        }
+        
+        let total_freq : f64 = freqs.iter().sum();
+        let target_freqs = settings::target_distribution(&freqs, total_freq, &[]);
+        
+        for a in 0..max_index
+        {
+            if forbidden_tiles.contains(&a) // FIXME: ?????????
+            {
+                continue;
+            }
+            // a tile that isn't in the example (one the caller's tile list has but the map never uses) has nothing to
+            // normalize; lint reports it
+            if freqs[a] == 0.0
             {
-                if forbidden_tiles.contains(&a) // FIXME: ?????????
+                continue;
+            }
+            for direction in 0..DIRECTIONS.len()
+            {
+                //for other in &ships[i][j]
+                //{
+                //    //total += other;
+                //}
+                let mut total = 0.0;
+                
+                //ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + direction]
+                for b in 0..max_index
                 {
-                    continue;
+                    let other = ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + direction];
+                    total += other;
                 }
-                for direction in 0..DIRECTIONS.len()
+                assert!(total > 0.0, "failed tile type {} direction {}", a, direction);
+                for b in 0..max_index
                 {
-                    //for other in &ships[i][j]
-                    //{
-                    //    //total += other;
-                    //}
-                    let mut total = 0.0;
-                    
-                    //ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + direction]
-                    for b in 0..max_index
-                    {
-                        let other = ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + direction];
-                        total += other;
-                    }
-                    assert!(total > 0.0, "failed tile type {} direction {}", a, direction);
-                    for b in 0..max_index
-                    {
-                        let other = &mut ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + direction];
-                        *other /= total;
-                    }
+                    let other = &mut ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + direction];
+                    *other /= total;
                 }
             }
-            
-            // ensure that all neighbor facts of form "A can exist to the right of B" match all facts of the form "B can exist to the left of A"
-            for a in 0..max_index
+        }
+        
+        // ensure that all neighbor facts of form "A can exist to the right of B" match all facts of the form "B can exist to the left of A"
+        for a in 0..max_index
+        {
+            for b in 0..max_index
             {
-                for b in 0..max_index
+                for dir in 0..DIRECTIONS.len()
                 {
-                    for dir in 0..DIRECTIONS.len()
+                    //ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + dir]
+                    let aw = ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + dir];
+                    let bw = ships[b*max_index*DIRECTIONS.len() + a*DIRECTIONS.len() + get_opposite_direction(dir)];
+                    if (aw == 0.0) != (bw == 0.0)
                     {
-                        //ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + dir]
-                        let aw = ships[a*max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + dir];
-                        let bw = ships[b*max_index*DIRECTIONS.len() + a*DIRECTIONS.len() + get_opposite_direction(dir)];
-                        if (aw == 0.0) != (bw == 0.0)
-                        {
-                            panic!("{} and {} don't match up for direction {} ({} vs {})", a, b, dir, aw, bw);
-                        }
+                        panic!("{} and {} don't match up for direction {} ({} vs {})", a, b, dir, aw, bw);
                     }
                 }
             }
-    
-            let mut width  = 10*4;
-            let mut height =  8*4;
-            //let mut width  = 10*2;
-            //let mut height =  8*2;
-            width  += 2;
-            height += 2;
-            //let width  = 8*8;
-            //let height = 8*8;
-            //let mut out_map : Vec<SuperTile> = vec!(SuperTile::Field(Default::default()); (width+2)*(height+2));
-            // one cell at a time, one direction at a time, one tile at a time, one row at a time
-            let out_map_fields = vec!(1.0; width*height*DIRECTIONS.len()*max_index);
-            let out_map_types  = vec!(TileType::Field; width*height);
-            
-            let candidates = Vec::new();
-            let damage = Vec::new();
-            
-            let out_freqs = vec!(0.0; max_index);
-            let out_total_freq = 0.0;
-            
-            let mut collapse = Collapser { max_index, id_to_tile, forbidden_tiles, freqs, total_freq, ships, most_common, out_map_fields, out_map_types, width, height, out_freqs, out_total_freq, damage, candidates, namebase };
-            
-            collapse.init_edges();
-            
-            collapse
         }
-        fn init_edges(&mut self)
+
+        let mut width  = 10*4;
+        let mut height =  8*4;
+        //let mut width  = 10*2;
+        //let mut height =  8*2;
+        width  += 2;
+        height += 2;
+        //let width  = 8*8;
+        //let height = 8*8;
+        //let mut out_map : Vec<SuperTile> = vec!(SuperTile::Field(Default::default()); (width+2)*(height+2));
+        
+        let mut collapse = Collapser { max_index, id_to_tile : Arc::new(id_to_tile), example : Arc::new(map), forbidden_tiles, freqs : Arc::new(freqs), total_freq, ships : Arc::new(ships), most_common, out_map_fields : Vec::new(), out_map_types : Vec::new(), possibilities : None, journal : Default::default(), width, height, out_freqs : Vec::new(), out_total_freq : 0.0, damage : Vec::new(), candidates : frontier::Frontier::new(0, 0), namebase, recorder : None, snapshots : true, layers : Arc::new(layers), tile_counts : Vec::new(), count_constraints : Vec::new(), possible_counts : Vec::new(), undecided : 0, walkable : Vec::new(), required_walkable : Vec::new(), walkable_anchors : None, walkable_pieces : 0, settings : Default::default(), target_freqs };
+        collapse.set_output_size(width, height);
+        collapse
+    }
+    // starts over with a blank output of the given size (border ring included) for the current solver;
+    // constraints have to be added afterwards
+    fn set_output_size(&mut self, width : usize, height : usize)
+    {
+        assert!(width >= 3 && height >= 3, "the output has to be at least 3x3 to fit anything inside its border");
+        self.width = width;
+        self.height = height;
+        // one cell at a time, one direction at a time, one tile at a time, one row at a time. the bitset solver works
+        // its fields out when it needs them, so it doesn't keep any
+        self.out_map_fields = match self.settings.solver
         {
-            for y in 0..self.height as isize
-            {
-                *self.get_type_mut(           0, y) = TileType::Tile(0);
-                *self.get_type_mut(self.width as isize-1, y) = TileType::Tile(0);
-                self.damage.push((1, y));
-                self.damage.push((self.width as isize-2, y));
-            }
-            for x in 0..self.width as isize
+            settings::Solver::Fields => vec!(1.0; width*height*DIRECTIONS.len()*self.max_index),
+            settings::Solver::Bitset => Vec::new(),
+        };
+        self.out_map_types  = vec!(TileType::Field; width*height);
+        self.journal = journal::Journal::new(width*height);
+        
+        self.candidates = frontier::Frontier::new(width, height);
+        self.damage = Vec::new();
+        
+        self.out_freqs = vec!(0.0; self.max_index);
+        self.out_total_freq = 0.0;
+        
+        self.init_edges();
+        self.count_tiles();
+        self.possibilities = match self.settings.solver
+        {
+            settings::Solver::Fields => None,
+            settings::Solver::Bitset => Some(bitset::Possibilities::new(&self.ships, self.max_index, &self.out_map_types, width, height)),
+        };
+    }
+    fn count_tiles(&mut self)
+    {
+        self.tile_counts = vec!(0; self.max_index);
+        for tile_type in self.out_map_types.iter()
+        {
+            if let TileType::Tile(id) = tile_type
             {
-                *self.get_type_mut(x,             0) = TileType::Tile(0);
-                *self.get_type_mut(x, self.height as isize-1) = TileType::Tile(0);
-                self.damage.push((x, 1));
-                self.damage.push((x, self.height as isize-2));
+                self.tile_counts[*id] += 1;
             }
         }
-        #[inline(always)]
-        fn get_type(&self, x : isize, y : isize) -> TileType
-        {
-            let x = x.wrapping_rem_euclid(self.width as isize) as usize;
-            let y = y.wrapping_rem_euclid(self.height as isize) as usize;
-            let index = y*self.width+x;
-            self.out_map_types[index]
-        }
-        #[inline(always)]
-        fn get_all_fields<'a>(&'a self, x : isize, y : isize) -> &'a [f64]
+    }
+    // catches everything up with cells that were pinned after set_output_size, before running
+    fn finish_pinning(&mut self)
+    {
+        self.count_tiles();
+        if self.possibilities.is_some()
         {
-            let x = x.wrapping_rem_euclid(self.width as isize) as usize;
-            let y = y.wrapping_rem_euclid(self.height as isize) as usize;
-            let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index;
-            let end_index = start_index + DIRECTIONS.len()*self.max_index;
-            &self.out_map_fields[start_index..end_index]
+            self.possibilities = Some(bitset::Possibilities::new(&self.ships, self.max_index, &self.out_map_types, self.width, self.height));
         }
-        //fn get_fields<'a>(&'a self, x : usize, y : usize, direction : usize) -> &'a [f64]
-        //{
-        //    let x = x%self.width;
-        //    let y = y%self.height;
-        //    let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index + direction*self.max_index;
-        //    let end_index = start_index + self.max_index;
-        //    &self.out_map_fields[start_index..end_index]
-        //}
-        #[inline(always)]
-        fn get_type_mut<'a>(&'a mut self, x : isize, y : isize) -> &'a mut TileType
+    }
+    // another collapser sharing this one's learned model, with copies of its settings, constraints and output so far
+    // (but no animation recording)
+    fn fork(&self, namebase : String) -> Collapser
+    {
+        Collapser
         {
-            let x = x.wrapping_rem_euclid(self.width as isize) as usize;
-            let y = y.wrapping_rem_euclid(self.height as isize) as usize;
-            let index = y*self.width+x;
-            &mut self.out_map_types[index]
+            max_index : self.max_index,
+            id_to_tile : self.id_to_tile.clone(),
+            example : self.example.clone(),
+            forbidden_tiles : self.forbidden_tiles.clone(),
+            freqs : self.freqs.clone(),
+            total_freq : self.total_freq,
+            ships : self.ships.clone(),
+            most_common : self.most_common,
+            width : self.width,
+            height : self.height,
+            out_map_fields : self.out_map_fields.clone(),
+            out_map_types : self.out_map_types.clone(),
+            possibilities : self.possibilities.clone(),
+            journal : self.journal.clone(),
+            damage : self.damage.clone(),
+            candidates : self.candidates.clone(),
+            out_freqs : self.out_freqs.clone(),
+            out_total_freq : self.out_total_freq,
+            namebase,
+            recorder : None,
+            snapshots : self.snapshots,
+            layers : self.layers.clone(),
+            tile_counts : self.tile_counts.clone(),
+            count_constraints : self.count_constraints.clone(),
+            possible_counts : self.possible_counts.clone(),
+            undecided : self.undecided,
+            walkable : self.walkable.clone(),
+            required_walkable : self.required_walkable.clone(),
+            walkable_anchors : self.walkable_anchors,
+            walkable_pieces : self.walkable_pieces,
+            settings : self.settings.clone(),
+            target_freqs : self.target_freqs.clone(),
         }
-        #[inline(always)]
-        fn get_all_fields_mut<'a>(&'a mut self, x : isize, y : isize) -> &'a mut [f64]
+    }
+    fn init_edges(&mut self)
+    {
+        for y in 0..self.height as isize
         {
-            let x = x.wrapping_rem_euclid(self.width as isize) as usize;
-            let y = y.wrapping_rem_euclid(self.height as isize) as usize;
-            let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index;
-            let end_index = start_index + DIRECTIONS.len()*self.max_index;
-            &mut self.out_map_fields[start_index..end_index]
+            *self.get_type_mut(           0, y) = TileType::Tile(0);
+            *self.get_type_mut(self.width as isize-1, y) = TileType::Tile(0);
+            self.damage.push((1, y));
+            self.damage.push((self.width as isize-2, y));
         }
-        //fn get_fields_mut<'a>(&'a mut self, x : usize, y : usize, direction : usize) -> &'a mut [f64]
-        //{
-        //    let x = x%self.width;
-        //    let y = y%self.height;
-        //    let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index + direction*self.max_index;
-        //    let end_index = start_index + self.max_index;
-        //    &mut self.out_map_fields[start_index..end_index]
-        //}
-        #[inline(always)]
-        fn edge_weight(&self, a : TileId, b : TileId, dir : usize) -> f64
+        for x in 0..self.width as isize
         {
-            //if self.forbidden_tiles.contains(&edge) { 0.0 }
-            //else { self.ships[origin][direction][edge] }
-            
-            let index = a*self.max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + dir;
-            //let index = index%self.ships.len();
-            //self.ships[index]
-            unsafe { *self.ships.get_unchecked(index) }
-            //self.ships[origin][direction][edge]
+            *self.get_type_mut(x,             0) = TileType::Tile(0);
+            *self.get_type_mut(x, self.height as isize-1) = TileType::Tile(0);
+            self.damage.push((x, 1));
+            self.damage.push((x, self.height as isize-2));
         }
-        #[inline(always)]
-        fn actual_weight(&self, (tile_type, fields) : (TileType, &[f64]), edge : TileId, direction : usize) -> f64
+    }
+    #[inline(always)]
+    fn get_type(&self, x : isize, y : isize) -> TileType
+    {
+        let x = x.wrapping_rem_euclid(self.width as isize) as usize;
+        let y = y.wrapping_rem_euclid(self.height as isize) as usize;
+        let index = y*self.width+x;
+        self.out_map_types[index]
+    }
+    #[inline(always)]
+    fn get_all_fields(&self, x : isize, y : isize) -> &[f64]
+    {
+        let x = x.wrapping_rem_euclid(self.width as isize) as usize;
+        let y = y.wrapping_rem_euclid(self.height as isize) as usize;
+        let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index;
+        let end_index = start_index + DIRECTIONS.len()*self.max_index;
+        &self.out_map_fields[start_index..end_index]
+    }
+    //fn get_fields<'a>(&'a self, x : usize, y : usize, direction : usize) -> &'a [f64]
+    //{
+    //    let x = x%self.width;
+    //    let y = y%self.height;
+    //    let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index + direction*self.max_index;
+    //    let end_index = start_index + self.max_index;
+    //    &self.out_map_fields[start_index..end_index]
+    //}
+    #[inline(always)]
+    fn get_type_mut(&mut self, x : isize, y : isize) -> &mut TileType
+    {
+        let x = x.wrapping_rem_euclid(self.width as isize) as usize;
+        let y = y.wrapping_rem_euclid(self.height as isize) as usize;
+        let index = y*self.width+x;
+        &mut self.out_map_types[index]
+    }
+    #[inline(always)]
+    fn get_all_fields_mut(&mut self, x : isize, y : isize) -> &mut [f64]
+    {
+        let x = x.wrapping_rem_euclid(self.width as isize) as usize;
+        let y = y.wrapping_rem_euclid(self.height as isize) as usize;
+        let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index;
+        let end_index = start_index + DIRECTIONS.len()*self.max_index;
+        self.journal.record(y*self.width+x, self.out_map_types[y*self.width+x], &self.out_map_fields[start_index..end_index]);
+        &mut self.out_map_fields[start_index..end_index]
+    }
+    //fn get_fields_mut<'a>(&'a mut self, x : usize, y : usize, direction : usize) -> &'a mut [f64]
+    //{
+    //    let x = x%self.width;
+    //    let y = y%self.height;
+    //    let start_index = (y*self.width+x)*DIRECTIONS.len()*self.max_index + direction*self.max_index;
+    //    let end_index = start_index + self.max_index;
+    //    &mut self.out_map_fields[start_index..end_index]
+    //}
+    #[inline(always)]
+    fn edge_weight(&self, a : TileId, b : TileId, dir : usize) -> f64
+    {
+        //if self.forbidden_tiles.contains(&edge) { 0.0 }
+        //else { self.ships[origin][direction][edge] }
+        
+        let index = a*self.max_index*DIRECTIONS.len() + b*DIRECTIONS.len() + dir;
+        //let index = index%self.ships.len();
+        //self.ships[index]
+        unsafe { *self.ships.get_unchecked(index) }
+        //self.ships[origin][direction][edge]
+    }
+    #[inline(always)]
+    #[allow(clippy::identity_op)]
+    fn actual_weight(&self, (tile_type, fields) : (TileType, &[f64]), edge : TileId, direction : usize) -> f64
+    {
+        match tile_type
         {
-            match tile_type
+            //TileType::Tile(id) => self.edge_weight(edge, id, get_opposite_direction(direction)), // ????????????
+            TileType::Tile(id) => self.edge_weight(id, edge, direction),
+            TileType::Field =>
             {
-                //TileType::Tile(id) => self.edge_weight(edge, id, get_opposite_direction(direction)), // ????????????
-                TileType::Tile(id) => self.edge_weight(id, edge, direction),
-                TileType::Field =>
+                //let opposite_direction = get_opposite_direction(direction);
+                let mut total = 0.0;
+                for id in 0..self.max_index
                 {
-                    //let opposite_direction = get_opposite_direction(direction);
-                    let mut total = 0.0;
-                    for id in 0..self.max_index
+                    //let mut glob : f64 = 1.0;
+                    //for i in 0..DIRECTIONS.len()
+                    //{
+                    //    glob *= fields[i + id*DIRECTIONS.len()];
+                    //}
+                    let glob;
+                    if DIRECTIONS.len() == 4 // optimizes away
                     {
-                        //let mut glob : f64 = 1.0;
-                        //for i in 0..DIRECTIONS.len()
-                        //{
-                        //    glob *= fields[i + id*DIRECTIONS.len()];
-                        //}
-                        let glob;
-                        if DIRECTIONS.len() == 4 // optimizes away
-                        {
-                            // yes this is slightly faster
-                            unsafe
-                            {
-                                glob = (*fields.get_unchecked(0 + id*DIRECTIONS.len()) * *fields.get_unchecked(2 + id*DIRECTIONS.len()))
-                                     * (*fields.get_unchecked(1 + id*DIRECTIONS.len()) * *fields.get_unchecked(3 + id*DIRECTIONS.len()));
-                            }
-                        }
-                        else if DIRECTIONS.len() == 8 // optimizes away
+                        // yes this is slightly faster
+                        unsafe
                         {
-                            unsafe
-                            {
-                                glob = ((*fields.get_unchecked(0 + id*DIRECTIONS.len()) * *fields.get_unchecked(4 + id*DIRECTIONS.len()))
-                                      * (*fields.get_unchecked(2 + id*DIRECTIONS.len()) * *fields.get_unchecked(6 + id*DIRECTIONS.len())))
-                                     * ((*fields.get_unchecked(1 + id*DIRECTIONS.len()) * *fields.get_unchecked(5 + id*DIRECTIONS.len()))
-                                      * (*fields.get_unchecked(3 + id*DIRECTIONS.len()) * *fields.get_unchecked(7 + id*DIRECTIONS.len())));
-                            }
+                            glob = (*fields.get_unchecked(0 + id*DIRECTIONS.len()) * *fields.get_unchecked(2 + id*DIRECTIONS.len()))
+                                 * (*fields.get_unchecked(1 + id*DIRECTIONS.len()) * *fields.get_unchecked(3 + id*DIRECTIONS.len()));
                         }
-                        else
+                    }
+                    else if DIRECTIONS.len() == 8 // optimizes away
+                    {
+                        unsafe
                         {
-                            panic!("DIRECTIONS must beeither four or eight elements long");
+                            glob = ((*fields.get_unchecked(0 + id*DIRECTIONS.len()) * *fields.get_unchecked(4 + id*DIRECTIONS.len()))
+                                  * (*fields.get_unchecked(2 + id*DIRECTIONS.len()) * *fields.get_unchecked(6 + id*DIRECTIONS.len())))
+                                 * ((*fields.get_unchecked(1 + id*DIRECTIONS.len()) * *fields.get_unchecked(5 + id*DIRECTIONS.len()))
+                                  * (*fields.get_unchecked(3 + id*DIRECTIONS.len()) * *fields.get_unchecked(7 + id*DIRECTIONS.len())));
                         }
-                        if glob != 0.0
+                    }
+                    else
+                    {
+                        panic!("DIRECTIONS must beeither four or eight elements long");
+                    }
+                    if glob != 0.0
+                    {
+                        unsafe
                         {
-                            unsafe
-                            {
-                                total += self.edge_weight(id, edge, direction) * *fields.get_unchecked(direction + id*DIRECTIONS.len());
-                            }
+                            total += self.edge_weight(id, edge, direction) * *fields.get_unchecked(direction + id*DIRECTIONS.len());
                         }
                     }
-                    total// / self.max_index as f64
                 }
-                _ => 1.0
+                total// / self.max_index as f64
             }
+            _ => 1.0
         }
-        
-        fn write_image<T : ToString + std::fmt::Display>(
-            &self,
-            namesuffix : T,
-            highlight : (isize, isize),
-            )
-        {
-            let mut out = DynamicImage::new_rgba8((self.width*TILESIZE) as u32, (self.height*TILESIZE) as u32);
-            let out_writer = out.as_mut_rgba8().unwrap();
-            let mut output_tile = vec!(RgbaF::new(0.0, 0.0, 0.0, 0.0); TILESIZE*TILESIZE);
-            for y in 0..self.height
+    }
+    
+    #[allow(clippy::identity_op)]
+    fn render_image(&self, highlight : (isize, isize)) -> image::RgbaImage
+    {
+        let mut out = image::RgbaImage::new((self.width*TILESIZE) as u32, (self.height*TILESIZE) as u32);
+        let out_writer = &mut out;
+        let mut output_tile = vec!(RgbaF::new(0.0, 0.0, 0.0, 0.0); TILESIZE*TILESIZE);
+        for y in 0..self.height
+        {
+            for x in 0..self.width
             {
-                for x in 0..self.width
+                let cell_type = self.get_type(x as isize, y as isize);
+                match cell_type
                 {
-                    let cell_type = self.get_type(x as isize, y as isize);
-                    match cell_type
+                    TileType::Tile(id) =>
                     {
-                        TileType::Tile(id) =>
+                        for ty in 0..TILESIZE
                         {
-                            for ty in 0..TILESIZE
+                            for tx in 0..TILESIZE
                             {
-                                for tx in 0..TILESIZE
-                                {
-                                    let px = &self.id_to_tile[id][ty*TILESIZE + tx];
-                                    let rgba = *image::Rgba::from_slice(&[px.r, px.g, px.b, px.a]);
-                                    out_writer.put_pixel((x*TILESIZE + tx) as u32, (y*TILESIZE + ty) as u32, rgba);
-                                }
+                                let px = &self.id_to_tile[id][ty*TILESIZE + tx];
+                                let rgba = *image::Rgba::from_slice(&[px.r, px.g, px.b, px.a]);
+                                out_writer.put_pixel((x*TILESIZE + tx) as u32, (y*TILESIZE + ty) as u32, rgba);
                             }
                         }
-                        TileType::Field =>
+                    }
+                    TileType::Field =>
+                    {
+                        // without fields, every tile the bitset solver still allows counts the same
+                        let cell_fields = match &self.possibilities
                         {
-                            let cell_fields = self.get_all_fields(x as isize, y as isize);
-                            output_tile.fill(RgbaF::new(0.0, 0.0, 0.0, 0.0));
-                            let mut control = 0.0;
-                            for id in 0..self.max_index
-                            {
-                                for j in 0..DIRECTIONS.len()
-                                { 
-                                    let f = cell_fields[j + id*DIRECTIONS.len()] as f32;
-                                    for ty in 0..TILESIZE
+                            Some(state) => (0..self.max_index*DIRECTIONS.len()).map(|i| state.has(y*self.width + x, i/DIRECTIONS.len()) as u8 as f64).collect(),
+                            None => self.get_all_fields(x as isize, y as isize).to_vec(),
+                        };
+                        output_tile.fill(RgbaF::new(0.0, 0.0, 0.0, 0.0));
+                        let mut control = 0.0;
+                        for id in 0..self.max_index
+                        {
+                            for j in 0..DIRECTIONS.len()
+                            { 
+                                let f = cell_fields[j + id*DIRECTIONS.len()] as f32;
+                                for ty in 0..TILESIZE
+                                {
+                                    for tx in 0..TILESIZE
                                     {
-                                        for tx in 0..TILESIZE
-                                        {
-                                            let px = &self.id_to_tile[id][ty*TILESIZE + tx];
-                                            output_tile[ty*TILESIZE + tx].add_mut(&px.to_float().mult(f));
-                                        }
+                                        let px = &self.id_to_tile[id][ty*TILESIZE + tx];
+                                        output_tile[ty*TILESIZE + tx].add_mut(&px.to_float().mult(f));
                                     }
-                                    control += f;
                                 }
+                                control += f;
                             }
-                            for ty in 0..TILESIZE
+                        }
+                        for ty in 0..TILESIZE
+                        {
+                            for tx in 0..TILESIZE
                             {
-                                for tx in 0..TILESIZE
+                                let mut px = output_tile[ty*TILESIZE + tx].mult(1.0/control);
+                                fn overlay (a : f32, b : f32) -> f32
                                 {
-                                    let mut px = output_tile[ty*TILESIZE + tx].mult(1.0/control as f32);
-                                    fn overlay (a : f32, b : f32) -> f32
+                                    if a < 0.5
+                                    {
+                                        2.0*a*b
+                                    }
+                                    else
                                     {
-                                        if a < 0.5
-                                        {
-                                            2.0*a*b
-                                        }
-                                        else
-                                        {
-                                            1.0-2.0*((1.0-a)*(1.0-b))
-                                        }
+                                        1.0-2.0*((1.0-a)*(1.0-b))
                                     }
-                                    px.r = overlay(px.r, 0.7);
-                                    px.g = overlay(px.g, 0.3);
-                                    px.b = overlay(px.b, 0.3);
-                                    let px = px.to_u8();
-                                    let rgba = *image::Rgba::from_slice(&[px.r, px.g, px.b, px.a]);
-                                    out_writer.put_pixel((x*TILESIZE + tx) as u32, (y*TILESIZE + ty) as u32, rgba);
                                 }
+                                px.r = overlay(px.r, 0.7);
+                                px.g = overlay(px.g, 0.3);
+                                px.b = overlay(px.b, 0.3);
+                                let px = px.to_u8();
+                                let rgba = *image::Rgba::from_slice(&[px.r, px.g, px.b, px.a]);
+                                out_writer.put_pixel((x*TILESIZE + tx) as u32, (y*TILESIZE + ty) as u32, rgba);
                             }
                         }
-                        _ => {}
                     }
-                    if (x, y) == (highlight.0 as usize, highlight.1 as usize)
+                    _ => {}
+                }
+                if (x, y) == (highlight.0 as usize, highlight.1 as usize)
+                {
+                    for ty in 0..TILESIZE
                     {
-                        for ty in 0..TILESIZE
-                        {
-                            let px = out_writer.get_pixel_mut((x*TILESIZE + 0         ) as u32, (y*TILESIZE + ty) as u32);
-                            px[0] = 255;
-                            px[2] = 255;
-                            px[3] = 255;
-                            let px = out_writer.get_pixel_mut((x*TILESIZE + TILESIZE-1) as u32, (y*TILESIZE + ty) as u32);
-                            px[0] = 255;
-                            px[2] = 255;
-                            px[3] = 255;
-                        }
-                        for tx in 0..TILESIZE
-                        {
-                            let px = out_writer.get_pixel_mut((x*TILESIZE + tx) as u32, (y*TILESIZE + 0         ) as u32);
-                            px[0] = 255;
-                            px[2] = 255;
-                            px[3] = 255;
-                            let px = out_writer.get_pixel_mut((x*TILESIZE + tx) as u32, (y*TILESIZE + TILESIZE-1) as u32);
-                            px[0] = 255;
-                            px[2] = 255;
-                            px[3] = 255;
-                        }
+                        let px = out_writer.get_pixel_mut((x*TILESIZE + 0         ) as u32, (y*TILESIZE + ty) as u32);
+                        px[0] = 255;
+                        px[2] = 255;
+                        px[3] = 255;
+                        let px = out_writer.get_pixel_mut((x*TILESIZE + TILESIZE-1) as u32, (y*TILESIZE + ty) as u32);
+                        px[0] = 255;
+                        px[2] = 255;
+                        px[3] = 255;
+                    }
+                    for tx in 0..TILESIZE
+                    {
+                        let px = out_writer.get_pixel_mut((x*TILESIZE + tx) as u32, (y*TILESIZE + 0         ) as u32);
+                        px[0] = 255;
+                        px[2] = 255;
+                        px[3] = 255;
+                        let px = out_writer.get_pixel_mut((x*TILESIZE + tx) as u32, (y*TILESIZE + TILESIZE-1) as u32);
+                        px[0] = 255;
+                        px[2] = 255;
+                        px[3] = 255;
                     }
                 }
             }
-            if format!("{}", namesuffix).as_str() != ""
+        }
+        out
+    }
+    fn write_image<T : ToString + std::fmt::Display>(
+        &mut self,
+        namesuffix : T,
+        highlight : (isize, isize),
+        )
+    {
+        let is_final = format!("{}", namesuffix).as_str() == "";
+        if !is_final && !self.snapshots
+        {
+            return;
+        }
+        let out = self.render_image(highlight);
+        if let Some(recorder) = &mut self.recorder
+        {
+            if is_final
             {
-                out.save(format!("{}_{}.png", self.namebase, namesuffix)).unwrap();
+                // hold the finished map on screen for a while before looping
+                recorder.push_with_delay(out.clone(), FINAL_FRAME_DELAY_MS);
             }
             else
             {
-                out.save(&self.namebase).unwrap();
+                recorder.push(out);
+                return;
             }
         }
-        fn recalculate (
-            &mut self,
-            scratch_fields : &mut Vec<f64>,
-            (x, y) : (isize, isize),
-            ) -> usize
+        if !is_final
+        {
+            out.save(format!("{}_{}.png", self.namebase, namesuffix)).unwrap();
+        }
+        else
+        {
+            out.save(&self.namebase).unwrap();
+        }
+    }
+    fn write_tiled(&self, stem : &str)
+    {
+        let mut map_layers = Vec::new();
+        for (i, layer) in self.layers.layers.iter().enumerate()
+        {
+            let image_path = if self.layers.len() == 1 { format!("{}_tileset.png", stem) } else { format!("{}_{}_tileset.png", stem, self.layers.file_tag(i)) };
+            export::write_atlas(&image_path, &layer.id_to_tile);
+            map_layers.push(tiled::MapLayer { name : &layer.name, types : self.layers.layer_types(i, &self.out_map_types), id_to_tile : &layer.id_to_tile, image_path });
+        }
+        tiled::write_tmj(&format!("{}.tmj", stem), self.width, self.height, &map_layers);
+        tiled::write_tmx(&format!("{}.tmx", stem), self.width, self.height, &map_layers);
+    }
+    fn write_grid(&self, stem : &str)
+    {
+        let atlas_path = format!("{}_atlas.png", stem);
+        export::write_atlas(&atlas_path, &self.id_to_tile);
+        export::write_grid_csv(&format!("{}.csv", stem), &self.out_map_types, self.width, self.height);
+        export::write_grid_json(&format!("{}.json", stem), &self.out_map_types, self.width, self.height, &atlas_path, self.max_index);
+        if self.layers.len() > 1
         {
-            let center_type = self.get_type(x, y);
-            if center_type != TileType::Field
+            // the grid above is of whole layer stacks; each layer also gets its own
+            for (i, layer) in self.layers.layers.iter().enumerate()
             {
-                return 0;
+                let layer_stem = format!("{}_{}", stem, self.layers.file_tag(i));
+                let types = self.layers.layer_types(i, &self.out_map_types);
+                let atlas_path = format!("{}_atlas.png", layer_stem);
+                export::write_atlas(&atlas_path, &layer.id_to_tile);
+                export::write_grid_csv(&format!("{}.csv", layer_stem), &types, self.width, self.height);
+                export::write_grid_json(&format!("{}.json", layer_stem), &types, self.width, self.height, &atlas_path, layer.id_to_tile.len());
             }
-            
-            let mut damaged = false;
-            let mut num_real_neighbors = 0;
-            // copy_from_slice
-            scratch_fields.copy_from_slice(self.get_all_fields(x, y));
-            
-            for offset in DIRECTIONS.iter()
+        }
+    }
+    fn write_layer_images(&self, stem : &str)
+    {
+        for (i, layer) in self.layers.layers.iter().enumerate()
+        {
+            let types = self.layers.layer_types(i, &self.out_map_types);
+            export::render_grid(&types, self.width, self.height, &layer.id_to_tile).save(format!("{}_{}.png", stem, self.layers.file_tag(i))).unwrap();
+        }
+    }
+    // the final image plus whichever of the other outputs were asked for, all named after stem
+    fn write_outputs(&mut self, stem : &str, write_tiled : bool, write_grid : bool)
+    {
+        self.write_image("", (-1, -1));
+        if self.layers.len() > 1
+        {
+            self.write_layer_images(stem);
+        }
+        if write_tiled
+        {
+            self.write_tiled(stem);
+        }
+        if write_grid
+        {
+            self.write_grid(stem);
+        }
+    }
+    // the cells that ended up dead, once the map is done
+    fn dead_cells(&self) -> Vec<(isize, isize)>
+    {
+        let mut dead_tiles = Vec::new();
+        for y in 0..self.height as isize
+        {
+            for x in 0..self.width as isize
             {
-                let neighbor_coord = (offset.0 + x, offset.1 + y);
-                let neighbor_type = self.get_type(neighbor_coord.0, neighbor_coord.1).clone();
-                if neighbor_type.is_real()
+                match self.get_type(x, y)
                 {
-                    num_real_neighbors += 1;
+                    TileType::Dead =>
+                        dead_tiles.push((x, y)),
+                    TileType::Field =>
+                        panic!("oops! an undecided tile was let through to the end of the algorithm! this indicates a bug with propagating probabilities"),
+                    _ => continue
                 }
             }
-            
-            let mut i = 0;
-            let neighbors = DIRECTIONS.map(|offset : (isize, isize)|
+        }
+        dead_tiles
+    }
+    fn record_gif(&mut self, path : &str, delay_ms : u32)
+    {
+        self.recorder = Some(anim::Recorder::new(path, delay_ms));
+    }
+    fn recalculate (
+        &mut self,
+        scratch_fields : &mut [f64],
+        (x, y) : (isize, isize),
+        ) -> usize
+    {
+        match self.recalculate_fields(scratch_fields, (x, y))
+        {
+            Some(outcome) => self.store_recalculation(scratch_fields, (x, y), outcome),
+            None => 0
+        }
+    }
+    // works out a cell's new fields into scratch_fields without changing anything, returning
+    // (damaged, dead, num_real_neighbors) for store_recalculation, or None if the cell isn't undecided
+    fn recalculate_fields (
+        &self,
+        scratch_fields : &mut [f64],
+        (x, y) : (isize, isize),
+        ) -> Option<(bool, bool, usize)>
+    {
+        let center_type = self.get_type(x, y);
+        if center_type != TileType::Field
+        {
+            return None;
+        }
+        
+        let mut damaged = false;
+        let mut num_real_neighbors = 0;
+        // copy_from_slice
+        scratch_fields.copy_from_slice(self.get_all_fields(x, y));
+        
+        for offset in DIRECTIONS.iter()
+        {
+            let neighbor_coord = (offset.0 + x, offset.1 + y);
+            let neighbor_type = self.get_type(neighbor_coord.0, neighbor_coord.1);
+            if neighbor_type.is_real()
             {
-                //let offset : (isize, isize) = DIRECTIONS[direction];
-                let neighbor_coord = (offset.0 + x, offset.1 + y);
-                let neighbor_type = self.get_type(neighbor_coord.0, neighbor_coord.1);
-                let neighbor_fields = self.get_all_fields(neighbor_coord.0, neighbor_coord.1);
-                i += 1;
-                (i-1, neighbor_type, neighbor_fields)
-            });
-            
-            for (direction, neighbor_type, neighbor_fields) in neighbors.iter()
+                num_real_neighbors += 1;
+            }
+        }
+        
+        let (chaos_floor, chaos_decay, chaos_blend) = (self.settings.chaos_floor, self.settings.chaos_decay, self.settings.chaos_blend);
+        let min_modifier = self.settings.min_modifier;
+        
+        let mut i = 0;
+        let neighbors = DIRECTIONS.map(|offset : (isize, isize)|
+        {
+            //let offset : (isize, isize) = DIRECTIONS[direction];
+            let neighbor_coord = (offset.0 + x, offset.1 + y);
+            let neighbor_type = self.get_type(neighbor_coord.0, neighbor_coord.1);
+            let neighbor_fields = self.get_all_fields(neighbor_coord.0, neighbor_coord.1);
+            i += 1;
+            (i-1, neighbor_type, neighbor_fields)
+        });
+        
+        for (direction, neighbor_type, neighbor_fields) in neighbors.iter()
+        {
+            for j in 0..self.max_index
             {
-                for j in 0..self.max_index
+                let direction = *direction;
+                let neighbor_type = *neighbor_type;
+                
+                let index = j*DIRECTIONS.len() + direction;
+                
+                //assert!(index < scratch_fields.len());
+                if scratch_fields[index] != 0.0
                 {
-                    let direction = *direction;
-                    let neighbor_type = *neighbor_type;
-                    
-                    let index = j*DIRECTIONS.len() + direction;
-                    
-                    //assert!(index < scratch_fields.len());
-                    if scratch_fields[index] != 0.0
+                    let fiddle = if j != 0 { 1.0 } else { 0.0 };
+                    let mut modifier = self.actual_weight((neighbor_type, neighbor_fields), j, get_opposite_direction(direction));
+                    modifier *= fiddle;
+                    if modifier != 0.0
                     {
-                        let fiddle = if j != 0 { 1.0 } else { 0.0 };
-                        let mut modifier = self.actual_weight((neighbor_type, &neighbor_fields), j, get_opposite_direction(direction));
-                        modifier *= fiddle;
-                        if modifier != 0.0
-                        {
-                            modifier = f64::max(modifier, 0.0001); // avoid making floats that, when raised to the 8th power, risk becoming subnormal
-                            
-                            let fiddle2 = if neighbor_type.is_real() { 1.0 } else { 0.0 };
-                            let fiddle3 = 1.0 - fiddle2;
-                            
-                            //scratch_fields[index] = fiddle2 * modifier + fiddle3 * ( 0.005 + scratch_fields[index]*0.985 + modifier*0.010);
-                            scratch_fields[index] = fiddle2 * modifier + fiddle3 * ( 0.005 + scratch_fields[index]*0.975 + modifier*0.020);
-                            
-                            //if neighbor_type.is_real()
-                            //{
-                            //    scratch_fields[index] = modifier;
-                            //}
-                            //else
-                            //{
-                            //    // controls the overall amount of chaos in the system; pure modifier is low-chaos
-                            //    //field[j] = field[j]*0.995 + modifier*0.005;
-                            //    //field[j] = field[j]*0.99 + modifier*0.01;
-                            //    //field[j] = field[j]*0.985 + modifier*0.015;
-                            //    //field[j] = field[j]*0.98 + modifier*0.02;
-                            //    //field[j] = field[j]*0.9 + modifier*0.1;
-                            //    //field[j] = 0.05 + field[j]*0.9 + modifier*0.05;
-                            //    scratch_fields[index] = 0.005 + scratch_fields[index]*0.985 + modifier*0.010;
-                            //    
-                            //    
-                            //    // unnecessary as long as the chaoticness expression has the 0.005 term in it
-                            //    // don't let numbers get so small they might get clamped to 0
-                            //    //if (scratch_fields[j].powi(8) as f32).is_subnormal()
-                            //    //{
-                            //    //    scratch_fields[j] = modifier;
-                            //    //}
-                            //}
-                        }
-                        else
-                        {
-                            scratch_fields[index] = 0.0;
-                            damaged = true;
-                        }
-                    }
-                }
-            }
-            
-            let fields = self.get_all_fields_mut(x, y);
-            fields.copy_from_slice(&scratch_fields[..]);
-            
-            if damaged
-            {
-                for dir in DIRECTIONS.iter()
-                {
-                    self.damage.push((x+dir.0, y+dir.1));
-                }
-            }
-            
-            let max_index = self.max_index;
-            let fields = self.get_all_fields_mut(x, y);
-            
-            let mut dead = false;
-            if damaged
-            {
-                dead = true;
-                for j in 0..max_index
-                {
-                    let mut f = 1.0;
-                    for i in 0..DIRECTIONS.len()
-                    {
-                        f *= fields[i + j*DIRECTIONS.len()];
-                    }
-                    if f == 0.0 || f.is_subnormal()
-                    {
-                        for i in 0..DIRECTIONS.len()
-                        {
-                            fields[i + j*DIRECTIONS.len()] = 0.0;
-                        }
-                        continue;
+                        modifier = f64::max(modifier, min_modifier); // avoid making floats that, when raised to the 8th power, risk becoming subnormal
+                        
+                        let fiddle2 = if neighbor_type.is_real() { 1.0 } else { 0.0 };
+                        let fiddle3 = 1.0 - fiddle2;
+                        
+                        //scratch_fields[index] = fiddle2 * modifier + fiddle3 * ( 0.005 + scratch_fields[index]*0.985 + modifier*0.010);
+                        scratch_fields[index] = fiddle2 * modifier + fiddle3 * ( chaos_floor + scratch_fields[index]*chaos_decay + modifier*chaos_blend);
+                        
+                        //if neighbor_type.is_real()
+                        //{
+                        //    scratch_fields[index] = modifier;
+                        //}
+                        //else
+                        //{
+                        //    // controls the overall amount of chaos in the system; pure modifier is low-chaos
+                        //    //field[j] = field[j]*0.995 + modifier*0.005;
+                        //    //field[j] = field[j]*0.99 + modifier*0.01;
+                        //    //field[j] = field[j]*0.985 + modifier*0.015;
+                        //    //field[j] = field[j]*0.98 + modifier*0.02;
+                        //    //field[j] = field[j]*0.9 + modifier*0.1;
+                        //    //field[j] = 0.05 + field[j]*0.9 + modifier*0.05;
+                        //    scratch_fields[index] = 0.005 + scratch_fields[index]*0.985 + modifier*0.010;
+                        //    
+                        //    
+                        //    // unnecessary as long as the chaoticness expression has the 0.005 term in it
+                        //    // don't let numbers get so small they might get clamped to 0
+                        //    //if (scratch_fields[j].powi(8) as f32).is_subnormal()
+                        //    //{
+                        //    //    scratch_fields[j] = modifier;
+                        //    //}
+                        //}
                     }
                     else
                     {
-                        dead = false;
+                        scratch_fields[index] = 0.0;
+                        damaged = true;
                     }
                 }
             }
-            
-            if num_real_neighbors > 0 && !self.candidates.contains(&(x, y))
-            {
-                self.candidates.push((x, y));
-            }
-            if damaged && dead
-            {
-                *self.get_type_mut(x, y) = TileType::Dead;
-                println!("!!!!---- killed tile at {},{}", x, y);
-                return 3;
-            }
-            else if damaged
-            {
-                return 2;
-            }
-            else
-            {
-                return 1;
-            }
         }
-    
-        fn recalculate_all (
-            &mut self,
-            scratch_fields : &mut Vec<f64>,
-            collapse_iteration : usize,
-            fail_early : bool,
-            retry_count : usize,
-            ) -> usize
-        {
-            let mut i = 0;
-            let mut i2 = 0;
-            let mut max_failstate = 0;
-            while !self.damage.is_empty()
+        
+        let max_index = self.max_index;
+        let fields = scratch_fields;
+        
+        let mut dead = false;
+        if damaged
+        {
+            dead = true;
+            for j in 0..max_index
             {
-                let choice = self.damage.pop().unwrap();
-                let failstate = self.recalculate(scratch_fields, choice);
-                max_failstate = std::cmp::max(max_failstate, failstate);
-                if failstate > 0
+                let mut f = 1.0;
+                for i in 0..DIRECTIONS.len()
                 {
-                    i += 1;
+                    f *= fields[i + j*DIRECTIONS.len()];
                 }
-                if failstate > 2
+                if f == 0.0 || f.is_subnormal()
                 {
-                    i2 += 1;
-                    self.write_image(format!("{}-{}-b{}", collapse_iteration.to_string(), retry_count, i2), choice);
-                    if fail_early
+                    for i in 0..DIRECTIONS.len()
                     {
-                        println!("recalculated {} tiles and short circuited", i);
-                        return failstate;   
+                        fields[i + j*DIRECTIONS.len()] = 0.0;
                     }
+                    continue;
+                }
+                else
+                {
+                    dead = false;
                 }
             }
-            println!("recalculated {} tiles", i);
-            max_failstate
         }
         
-        fn add_to_freq(&mut self, id : TileId)
-        {
-            self.out_freqs[id] += 1.0;
-            self.out_total_freq += 1.0;
-        }
-        fn get_freq(&self, id : TileId) -> f64
+        Some((damaged, dead, num_real_neighbors))
+    }
+    fn store_recalculation (
+        &mut self,
+        scratch_fields : &[f64],
+        (x, y) : (isize, isize),
+        (damaged, dead, num_real_neighbors) : (bool, bool, usize),
+        ) -> usize
+    {
+        let fields = self.get_all_fields_mut(x, y);
+        fields.copy_from_slice(scratch_fields);
+        
+        if damaged
         {
-            if self.out_total_freq > 0.0
-            {
-                (self.out_freqs[id]+1.0)/self.out_total_freq
-            }
-            else
+            for dir in DIRECTIONS.iter()
             {
-                1.0
+                self.damage.push((x+dir.0, y+dir.1));
             }
         }
-        fn get_freq_multiplier(&self, id : TileId) -> f64
+        
+        if num_real_neighbors > 0 && !self.candidates.contains((x, y))
+        {
+            self.candidates.push((x, y));
+        }
+        if damaged && dead
+        {
+            *self.get_type_mut(x, y) = TileType::Dead;
+            println!("!!!!---- killed tile at {},{}", x, y);
+            3
+        }
+        else if damaged
+        {
+            2
+        }
+        else
+        {
+            1
+        }
+    }
+
+    fn recalculate_all (
+        &mut self,
+        scratch_fields : &mut [f64],
+        collapse_iteration : usize,
+        fail_early : bool,
+        retry_count : usize,
+        ) -> usize
+    {
+        if self.settings.threads > 1
+        {
+            return self.recalculate_all_waves(collapse_iteration, fail_early, retry_count);
+        }
+        let mut i = 0;
+        let mut i2 = 0;
+        let mut max_failstate = 0;
+        while let Some(choice) = self.damage.pop()
         {
-            let truth = self.freqs[id]/self.total_freq;
-            let local = self.get_freq(id);
-            1000000000.0 * if local == 0.0
+            let failstate = self.recalculate(scratch_fields, choice);
+            max_failstate = std::cmp::max(max_failstate, failstate);
+            if failstate > 0
             {
-                1.0
+                i += 1;
             }
-            else
+            if failstate > 2
             {
-                truth/local
+                i2 += 1;
+                self.write_image(format!("{}-{}-b{}", collapse_iteration, retry_count, i2), choice);
+                if fail_early
+                {
+                    println!("recalculated {} tiles and short circuited", i);
+                    return failstate;   
+                }
             }
         }
-        fn collapse (
-            &mut self,
-            scratch_fields : &mut Vec<f64>,
-            collapse_iteration : usize,
-            choice : (isize, isize),
-            rng : &mut oorandom::Rand64,
-            retry_count : usize
-            ) -> bool
+        println!("recalculated {} tiles", i);
+        max_failstate
+    }
+    
+    fn add_to_freq(&mut self, id : TileId)
+    {
+        self.out_freqs[id] += 1.0;
+        self.out_total_freq += 1.0;
+    }
+    fn get_freq(&self, id : TileId) -> f64
+    {
+        if self.out_total_freq > 0.0
+        {
+            (self.out_freqs[id]+1.0)/self.out_total_freq
+        }
+        else
+        {
+            1.0
+        }
+    }
+    fn apply_settings(&mut self, settings : settings::Settings)
+    {
+        self.target_freqs = settings::target_distribution(&self.freqs, self.total_freq, &settings.target_freqs);
+        self.settings = settings;
+    }
+    fn get_freq_multiplier(&self, id : TileId) -> f64
+    {
+        if !self.settings.freq_correction
+        {
+            return FREQ_MULTIPLIER_SCALE;
+        }
+        let truth = self.target_freqs[id];
+        let local = self.get_freq(id);
+        FREQ_MULTIPLIER_SCALE * if local == 0.0
+        {
+            1.0
+        }
+        else if self.settings.freq_strength == 1.0
+        {
+            truth/local
+        }
+        else
+        {
+            portable::powf(truth/local, self.settings.freq_strength)
+        }
+    }
+    // how likely a tile is to be picked for a cell, relative to the others
+    fn get_weight(&self, fields : &[f64], id : TileId) -> f64
+    {
+        let mut f = self.get_freq_multiplier(id);
+        for dir in 0..DIRECTIONS.len()
+        {
+            f *= fields[dir + id*DIRECTIONS.len()];
+        }
+        f * self.get_count_multiplier(id)
+    }
+    // picks a tile out of every tile's weight for a cell according to the sampling settings; 0 if nothing is possible
+    fn sample<R : rng::Rng>(&self, weights : &[f64], rng : &mut R) -> TileId
+    {
+        let mut decision = 0;
+        
+        let mut total = 0.0;
+        let mut max_weight = 0.0;
+        let mut possible_fields = Vec::new();
+        
+        for (i, f) in weights.iter().enumerate()
         {
-            if self.get_type(choice.0, choice.1) != TileType::Field
+            total += f;
+            max_weight = f64::max(max_weight, *f);
+            if *f != 0.0
             {
-                return false;
+                possible_fields.push(i);
             }
-            let fields = self.get_all_fields(choice.0, choice.1);
-            let mut decision = 0;
-            
-            let mut total = 0.0;
-            let mut possible_fields = Vec::new();
-            
-            for i in 0..self.max_index
+        }
+        let temperature = self.settings.temperature;
+        match self.settings.sampling
+        {
+            settings::Sampling::Uniform =>
             {
-                let mut f = self.get_freq_multiplier(i);
-                for dir in 0..DIRECTIONS.len()
-                {
-                    f *= fields[dir + i*DIRECTIONS.len()];
-                }
-                total += f;
-                if f != 0.0
+                if !possible_fields.is_empty()
                 {
-                    possible_fields.push(i);
+                    decision = possible_fields[rng.rand_range(0..possible_fields.len() as u64) as usize];
                 }
             }
-            let force = possible_fields.len() < 2;
-            if false
+            settings::Sampling::Weighted if temperature == 0.0 =>
             {
-                if possible_fields.len() > 0
+                // the most likely tile, first one wins ties
+                if let Some(i) = possible_fields.iter().find(|i| weights[**i] == max_weight)
                 {
-                    decision = possible_fields[rng.rand_range(0..possible_fields.len() as u64) as usize];
+                    decision = *i;
                 }
             }
-            else
+            settings::Sampling::Weighted if max_weight == 0.0 =>
+            {
+                // nothing is possible (every tile left is capped or has a target of 0), and there's nothing to temper
+            }
+            settings::Sampling::Weighted =>
             {
+                // weights are scaled down to at most 1 before being raised to 1/temperature so they can't overflow
+                let temper = |f : f64| if temperature == 1.0 { f } else { portable::powf(f/max_weight, 1.0/temperature) };
+                if temperature != 1.0
+                {
+                    total = weights.iter().map(|f| temper(*f)).sum();
+                }
                 let n = rng.rand_float()*total;
                 //assert!(total > 0.0);
                 let mut total = 0.0;
-                for i in 0..self.max_index
+                for (i, f) in weights.iter().enumerate()
                 {
-                    let mut f = self.get_freq_multiplier(i);
-                    for dir in 0..DIRECTIONS.len()
-                    {
-                        f *= fields[dir + i*DIRECTIONS.len()];
-                    }
+                    let f = temper(*f);
                     if f == 0.0
                     {
                         continue;
@@ -889,150 +1199,703 @@ fn main() {
                 }
                 //assert!(decision != 0);
             }
-            if decision == 0
+        }
+        decision
+    }
+    #[allow(clippy::overly_complex_bool_expr)]
+    fn collapse<R : rng::Rng> (
+        &mut self,
+        scratch_fields : &mut [f64],
+        collapse_iteration : usize,
+        choice : (isize, isize),
+        rng : &mut R,
+        retry_count : usize
+        ) -> bool
+    {
+        if self.get_type(choice.0, choice.1) != TileType::Field
+        {
+            return false;
+        }
+        let fields = self.get_all_fields(choice.0, choice.1);
+        let weights = (0..self.max_index).map(|i| self.get_weight(fields, i)).collect::<Vec<_>>();
+        let force = weights.iter().filter(|f| **f != 0.0).count() < 2;
+        let mut decision = self.sample(&weights, rng);
+        if decision == 0 && (1..self.max_index).any(|i| self.is_capped(i) && constraints::fields_allow(fields, i))
+        {
+            // nothing left but tiles that hit their maximum count
+            println!("!!!!---- killed tile at {},{}", choice.0, choice.1);
+            self.begin_journal();
+            self.journal_cell(choice);
+            *self.get_type_mut(choice.0, choice.1) = TileType::Dead;
+            for dir in DIRECTIONS.iter()
+            {
+                self.damage.push((choice.0 + dir.0, choice.1 + dir.1));
+            }
+            self.recalculate_all(scratch_fields, collapse_iteration, false, retry_count);
+            self.update_walkable();
+            self.end_journal();
+            return false;
+        }
+        if decision == 0
+        {
+            println!("!!!!!===== picking a random candidate failed, using the most common tiles");
+            println!("!!!!!===== (this means probability recalculation or damage tracking has a bug somewhere!)");
+            decision = self.most_common;
+            // FIXME: use a random neighbor instead?
+            // this is a fallback case though (being here means that probability recalculation has a bug)
+        }
+        //let old_map_types = self.out_map_types.clone();
+        // damage is always drained by the time a cell gets collapsed, and candidates only get added to while
+        // recalculating, so undoing a failed attempt takes rolling back the fields it touched and dropping what it added
+        assert!(self.damage.is_empty());
+        let candidates_mark = self.candidates.mark();
+        self.begin_journal();
+        self.journal_cell(choice);
+        *self.get_type_mut(choice.0, choice.1) = TileType::Tile(decision);
+        
+        //let comp = std::cmp::max(1, ((self.width*self.height) as f32/64.0).floor() as usize);
+        //let comp = std::cmp::max(1, ((self.width*self.height) as f32/32.0).floor() as usize);
+        //let comp = std::cmp::max(1, ((self.width*self.height) as f32/16.0).floor() as usize);
+        let comp = std::cmp::max(1, ((self.width*self.height) as f32/8.0).floor() as usize);
+        //let comp = 1;
+        
+        if false && collapse_iteration.is_multiple_of(comp)
+        {
+            println!("writing image for {}", collapse_iteration);
+            self.write_image(format!("{}-a1", collapse_iteration), choice);
+            println!("wrote image");
+        }
+        for dir in DIRECTIONS.iter()
+        {
+            self.damage.push((choice.0 + dir.0, choice.1 + dir.1));
+        }
+        let mut failed = self.recalculate_all(scratch_fields, collapse_iteration, !force, retry_count) == 3;
+        let mut new_walkable = 0;
+        if !failed
+        {
+            match self.walkable_change()
             {
-                println!("!!!!!===== picking a random candidate failed, using the most common tiles");
-                println!("!!!!!===== (this means probability recalculation or damage tracking has a bug somewhere!)");
-                decision = self.most_common;
-                // FIXME: use a random neighbor instead?
-                // this is a fallback case though (being here means that probability recalculation has a bug)
+                Some(count) => new_walkable = count,
+                None =>
+                {
+                    println!("!!!--- tile {} at {},{} would cut off part of the walkable area", decision, choice.0, choice.1);
+                    failed = true;
+                }
             }
-            //let old_map_types = self.out_map_types.clone();
-            let old_map_fields = self.out_map_fields.clone();
-            let old_damage = self.damage.clone();
-            let old_candidates = self.candidates.clone();
-            *self.get_type_mut(choice.0, choice.1) = TileType::Tile(decision);
+        }
+        if !failed && self.tracks_minimums() && self.breaks_minimums(&self.journal_possible_change(), decision)
+        {
+            println!("!!!--- tile {} at {},{} would leave too few cells for a minimum count", decision, choice.0, choice.1);
+            failed = true;
+        }
+        if force && failed
+        {
+            println!("!!!--- failed with tile {} at {},{}, but forced to live with it", decision, choice.0, choice.1);
+            self.end_journal();
+            // whatever it did to the walkable area wasn't looked at or was a split, so start over from scratch
+            self.count_walkable_anchors();
+            self.count_placed_tile(decision);
+        }
+        else if failed
+        {
+            println!("!!!--- failed with tile {} at {},{}, retrying", decision, choice.0, choice.1);
+            *self.get_type_mut(choice.0, choice.1) = TileType::Field;
+            //self.out_map_types = old_map_types;
+            self.rollback_journal();
+            self.damage.clear();
+            self.candidates.truncate(candidates_mark);
+            self.candidates.push(choice);
+            
+            println!("invalidating decision {} at {},{}", decision, choice.0, choice.1);
             
-            //let comp = std::cmp::max(1, ((self.width*self.height) as f32/64.0).floor() as usize);
-            //let comp = std::cmp::max(1, ((self.width*self.height) as f32/32.0).floor() as usize);
-            //let comp = std::cmp::max(1, ((self.width*self.height) as f32/16.0).floor() as usize);
-            let comp = std::cmp::max(1, ((self.width*self.height) as f32/8.0).floor() as usize);
-            //let comp = 1;
+            // journaled only to keep the minimum counts and the walkable area in step; this part never gets rolled back
+            self.begin_journal();
+            self.damage.push((choice.0, choice.1));
+            for (i, dir) in DIRECTIONS.iter().enumerate()
+            {
+            // uncomment if invalidation doesn't work properly
+                self.get_all_fields_mut(choice.0, choice.1)
+                [decision*DIRECTIONS.len() + i] = 0.0;
+                self.get_all_fields_mut(choice.0 + dir.0, choice.1 + dir.1)
+                [decision*DIRECTIONS.len() + get_opposite_direction(i)] = 0.0;
+            // uncomment if invalidation doesn't work properly
+                self.damage.push((choice.0 + dir.0, choice.1 + dir.1));
+            }
             
-            if false && collapse_iteration%comp == 0
+            self.recalculate_all(scratch_fields, collapse_iteration, false, retry_count);
+            self.update_walkable();
+            self.end_journal();
+        }
+        else
+        {
+            self.end_journal();
+            if let Some(anchors) = self.walkable_anchors.as_mut()
+            {
+                *anchors += new_walkable;
+            }
+            self.add_to_freq(decision);
+            self.count_placed_tile(decision);
+            if collapse_iteration.is_multiple_of(comp)
             {
                 println!("writing image for {}", collapse_iteration);
-                self.write_image(format!("{}-a1", collapse_iteration.to_string()), choice);
+                self.write_image(collapse_iteration.to_string(), choice);
                 println!("wrote image");
             }
-            for dir in DIRECTIONS.iter()
+        }
+        failed
+    }
+    // fills in every undecided cell. the result depends only on the example, the settings, the constraints, the
+    // output size and the seed: the solver only ever does + - * / on floats, in a fixed order, and anything fancier
+    // goes through portable, so the same inputs give the same map on every run and every platform (as long as floats
+    // are plain ieee doubles, which rules out x87-only 32-bit x86). src/tests.rs pins this down
+    fn run(&mut self, seed : u128)
+    {
+        self.run_with(&mut oorandom::Rand64::new(seed));
+    }
+    fn run_with<R : rng::Rng>(&mut self, rng : &mut R)
+    {
+        let collapse_iteration = self.begin_run();
+        self.continue_run(rng, collapse_iteration, &mut |_, _, _| {});
+    }
+    // the first pass over whatever's pinned, before anything gets collapsed; returns the first collapse's iteration
+    fn begin_run(&mut self) -> usize
+    {
+        if self.possibilities.is_some()
+        {
+            self.begin_bitset();
+            return 1;
+        }
+        let mut scratch_fields = vec!(1.0; self.max_index*DIRECTIONS.len());
+        self.recalculate_all(&mut scratch_fields, 0, false, 0);
+        1
+    }
+    // collapses candidates until there are none left, starting from begin_run or a checkpoint (which is also where a
+    // restart for a split walkable area goes back to). after_step gets called after every cell with the rng and the
+    // next collapse's iteration, which is everything a checkpoint needs
+    fn continue_run<R : rng::Rng>(&mut self, rng : &mut R, mut collapse_iteration : usize, after_step : &mut dyn FnMut(&Collapser, &R, usize))
+    {
+        self.count_possible();
+        self.count_walkable_anchors();
+        // pinned cells may already have put some tiles at their maximum
+        for id in 0..self.max_index
+        {
+            if self.is_capped(id)
             {
-                self.damage.push((choice.0 + dir.0, choice.1 + dir.1));
+                self.ban_tile(id);
             }
-            let failed = self.recalculate_all(scratch_fields, collapse_iteration, !force, retry_count) == 3;
-            if force && failed
+        }
+        // nothing a decision does is allowed to split the walkable area, but forced decisions and dying cells can do it
+        // anyway, and there's no backing out of those. so a run that ends up split starts over from here, with the rng
+        // wherever it got to, up to CONNECTIVITY_RESTARTS times. one that starts out split can't be helped
+        let start = self.walkable_anchors.is_some().then(|| self.fork(self.namebase.clone()));
+        let first_iteration = collapse_iteration;
+        let mut restarts = 0;
+        loop
+        {
+            self.collapse_candidates(rng, collapse_iteration, after_step);
+            let Some(start) = &start else { return };
+            if self.walkable_components() <= 1
             {
-                println!("!!!--- failed with tile {} at {},{}, but forced to live with it", decision, choice.0, choice.1);
+                return;
             }
-            else if failed
+            if restarts == connectivity::CONNECTIVITY_RESTARTS
             {
-                println!("!!!--- failed with tile {} at {},{}, retrying", decision, choice.0, choice.1);
-                *self.get_type_mut(choice.0, choice.1) = TileType::Field;
-                //self.out_map_types = old_map_types;
-                self.out_map_fields = old_map_fields;
-                self.damage = old_damage;
-                self.candidates = old_candidates;
-                self.candidates.push(choice);
-                
-                println!("invalidating decision {} at {},{}", decision, choice.0, choice.1);
-                
-                self.damage.push((choice.0, choice.1));
-                for (i, dir) in DIRECTIONS.iter().enumerate()
+                println!("!!!!---- the walkable area still came out split after {} restarts", restarts);
+                return;
+            }
+            restarts += 1;
+            println!("!!!--- the walkable area came out split, restarting ({} of {})", restarts, connectivity::CONNECTIVITY_RESTARTS);
+            let recorder = self.recorder.take();
+            *self = start.fork(self.namebase.clone());
+            self.recorder = recorder;
+            collapse_iteration = first_iteration;
+        }
+    }
+    fn collapse_candidates<R : rng::Rng>(&mut self, rng : &mut R, mut collapse_iteration : usize, after_step : &mut dyn FnMut(&Collapser, &R, usize))
+    {
+        if self.possibilities.is_some()
+        {
+            while !self.candidates.is_empty()
+            {
+                let choice_index = rng.rand_range(0..self.candidates.len() as u64) as usize;
+                let choice = self.candidates.remove(choice_index);
+                while self.collapse_bitset(collapse_iteration, choice, rng)
                 {
-                // uncomment if invalidation doesn't work properly
-                    self.get_all_fields_mut(choice.0, choice.1)
-                    [decision*DIRECTIONS.len() + i] = 0.0;
-                    self.get_all_fields_mut(choice.0 + dir.0, choice.1 + dir.1)
-                    [decision*DIRECTIONS.len() + get_opposite_direction(i)] = 0.0;
-                // uncomment if invalidation doesn't work properly
-                    self.damage.push((choice.0 + dir.0, choice.1 + dir.1));
                 }
-                
-                self.recalculate_all(scratch_fields, collapse_iteration, false, retry_count);
+                collapse_iteration += 1;
+                after_step(self, rng, collapse_iteration);
             }
-            else
+            return;
+        }
+        let mut scratch_fields = vec!(1.0; self.max_index*DIRECTIONS.len());
+        
+        if false
+        {
+            for y in 0..self.height
             {
-                self.add_to_freq(decision);
-                if collapse_iteration%comp == 0
+                for x in 0..self.width
                 {
-                    println!("writing image for {}", collapse_iteration);
-                    self.write_image(&collapse_iteration.to_string(), choice);
-                    println!("wrote image");
+                    let choice = (x as isize, y as isize);
+                    let mut i = 0;
+                    while self.collapse(&mut scratch_fields, collapse_iteration, choice, rng, i)
+                    {
+                        i += 1;
+                    }
+                    collapse_iteration += 1;
                 }
             }
-            return failed;
         }
-    }
-    
-    
-    //fn init(px_map : &Vec<Rgba>, width : usize, height : usize, namebase : String) -> Collapser
-    
-    let mut collapse_iteration = 0;
-    let mut collapser = Collapser::init(&px_map, img.width() as usize, img.height() as usize, args[2].to_string());
-    let mut scratch_fields = vec!(1.0; collapser.max_index*DIRECTIONS.len());
-    collapser.recalculate_all(&mut scratch_fields, collapse_iteration, false, 0);
-    collapse_iteration += 1;
-    
-    
-    #[allow(unused_variables)]
-    let time = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis();
-    //let time = 1627830772369;
-    //let time = 1627832395045;
-    //let time = 1627836423901;
-    //let time = 1627901131263;
-    //let time = 1627919001951;
-    //let time = 1627923822683;
-    //let time = 1628290023690;
-    let time = 1628290385006;
-    println!("seed: {}", time);
-    let mut rng = oorandom::Rand64::new(time);
-    
-    if false
-    {
-        for y in 0..collapser.height
+        else
         {
-            for x in 0..collapser.width
+            while !self.candidates.is_empty()
             {
-                let choice = (x as isize, y as isize);
+                let choice_index = rng.rand_range(0..self.candidates.len() as u64) as usize;
+                let choice = self.candidates.remove(choice_index);
                 let mut i = 0;
-                while collapser.collapse(&mut scratch_fields, collapse_iteration, choice, &mut rng, i)
+                while self.collapse(&mut scratch_fields, collapse_iteration, choice, rng, i)
                 {
                     i += 1;
                 }
                 collapse_iteration += 1;
+                after_step(self, rng, collapse_iteration);
             }
         }
     }
-    else
+}
+
+
+// splits an image into tiles, giving each distinct block of pixels its own id
+fn dedupe_tiles(px_map : &[Vec<Rgba>], width : usize, height : usize) -> (Vec<Vec<TileId>>, Vec<Vec<Rgba>>)
+{
+    let mut max_index = 0;
+    let mut tile_to_id = BTreeMap::new();
+    let mut id_to_tile = Vec::new();
+    let mut map = Vec::with_capacity(height/TILESIZE);
+    for y in 0..height/TILESIZE
+    {
+        let mut row = Vec::with_capacity(width/TILESIZE);
+        for x in 0..width/TILESIZE
+        {
+            let tile = get_tile_from_image(px_map, (x, y));
+            let id = tile_to_id.entry(tile.clone()).or_insert_with(|| {max_index += 1; max_index - 1});
+            if *id >= id_to_tile.len()
+            {
+                id_to_tile.push(tile);
+            }
+            
+            row.push(*id);
+            //print!("{} ", *id);
+        }
+        //println!();
+        map.push(row);
+    }
+    (map, id_to_tile)
+}
+
+fn load_px_map(path : &std::path::Path) -> Vec<Vec<Rgba>>
+{
+    let img = ImageReader::open(path).unwrap_or_else(|e| panic!("failed to open {}: {}", path.display(), e)).decode().unwrap();
+    let mut px_map = Vec::with_capacity(img.height() as usize);
+    for y in 0..img.height()
+    {
+        let mut row = Vec::with_capacity(img.width() as usize);
+        for x in 0..img.width()
+        {
+            let px : image::Rgba<u8> = img.get_pixel(x, y);
+            row.push(Rgba::new(px[0], px[1], px[2], px[3]));
+        }
+        px_map.push(row);
+    }
+    px_map
+}
+
+fn main() {
+    let args = args().collect::<Vec<String>>();
+    assert!(args.len() >= 3);
+    
+    
+    
+    //fn init(px_map : &Vec<Rgba>, width : usize, height : usize, namebase : String) -> Collapser
+    
+    let mut gif_delay = None;
+    let mut write_tiled = false;
+    let mut write_grid = false;
+    let mut extra_layers = Vec::new();
+    let mut count_constraints = Vec::new();
+    let mut settings = settings::Settings::default();
+    let mut walkable = Vec::new();
+    let mut required_walkable = Vec::new();
+    //let seed = 1627830772369;
+    //let seed = 1627832395045;
+    //let seed = 1627836423901;
+    //let seed = 1627901131263;
+    //let seed = 1627919001951;
+    //let seed = 1627923822683;
+    //let seed = 1628290023690;
+    let mut seed = 1628290385006;
+    let mut size = None;
+    let mut snapshots = true;
+    let mut batch = None;
+    let mut batch_threads = None;
+    let mut chunks = None;
+    let mut extend = None;
+    let mut grow = None;
+    let mut reroll = None;
+    let mut region = None;
+    let mut mask = None;
+    let mut checkpoint_every = None;
+    let mut resume = None;
+    let mut validate = false;
+    let mut check = None;
+    let mut stats = None;
+    let mut write_rules = false;
+    let mut write_lint = false;
+    let mut i = 3;
+    while i < args.len()
     {
-        while !collapser.candidates.is_empty()
+        match args[i].as_str()
         {
-            let choice_index = rng.rand_range(0..collapser.candidates.len() as u64) as usize;
-            let choice = collapser.candidates.remove(choice_index);
-            let mut i = 0;
-            while collapser.collapse(&mut scratch_fields, collapse_iteration, choice, &mut rng, i)
+            // record the intermediate snapshots into an animated gif instead of separate pngs
+            "--gif" => gif_delay = Some(DEFAULT_FRAME_DELAY_MS),
+            "--gif-delay" =>
+            {
+                i += 1;
+                gif_delay = Some(args.get(i).expect("--gif-delay needs a value in milliseconds").parse().unwrap());
+            }
+            // also write the finished map as a tiled map (.tmj and .tmx) plus its tileset image
+            "--tiled" => write_tiled = true,
+            // also write the raw TileId grid (.csv and .json) plus an atlas with each tile once
+            "--grid" => write_grid = true,
+            // another png of the same size to stack on top of the input (and any earlier layers); the layers are learned
+            // together, as stacks, so only combinations found in the example get generated
+            "--layer" =>
+            {
+                i += 1;
+                extra_layers.push(args.get(i).expect("--layer needs an image").clone());
+            }
+            // tile:min:max, a hard limit on how many times a tile may appear; either bound can be left empty
+            "--count" =>
+            {
+                i += 1;
+                count_constraints.push(constraints::CountConstraint::parse(args.get(i).expect("--count needs tile:min:max")));
+            }
+            // comma-separated tile ids; all cells with these tiles will form one connected area
+            "--walkable" =>
+            {
+                i += 1;
+                walkable = args.get(i).expect("--walkable needs a list of tile ids").split(',').map(|s| s.trim().parse().unwrap()).collect();
+            }
+            // x,y:x,y, two cells that have to be walkable (and so connected to each other)
+            "--path" =>
+            {
+                i += 1;
+                let coords = args.get(i).expect("--path needs x,y:x,y").split(':').map(|p|
+                {
+                    let p = p.split(',').map(|s| s.trim().parse::<isize>().unwrap()).collect::<Vec<_>>();
+                    assert!(p.len() == 2, "--path needs x,y:x,y");
+                    (p[0], p[1])
+                }).collect::<Vec<_>>();
+                assert!(coords.len() == 2, "--path needs x,y:x,y");
+                required_walkable.extend(coords);
+            }
+            // key = value lines with the same keys as the settings flags below
+            "--config" =>
             {
                 i += 1;
+                settings.load_config(args.get(i).expect("--config needs a file"));
             }
-            collapse_iteration += 1;
+            // --target takes id:share or id:xscale and overrides how common a tile should be in the output;
+            // --preset is one of settings::PRESETS, which set the chaos terms; --sampling is weighted or uniform;
+            // --solver is fields or bitset; --threads above 1 propagates the fields solver in parallel waves, which
+            // makes different maps from --threads 1 (but the same ones for any count above 1), and --min-parallel-work
+            // keeps waves smaller than that on one thread
+            "--threads" | "--min-parallel-work" | "--solver" | "--sampling" | "--temperature" | "--preset" | "--chaos-floor" | "--chaos-decay" | "--chaos-blend" | "--min-modifier" | "--freq-strength" | "--target" =>
+            {
+                let key = args[i][2..].replace('-', "_");
+                i += 1;
+                settings.set(&key, args.get(i).unwrap_or_else(|| panic!("--{} needs a value", key)));
+            }
+            // pick tiles purely by their local weights, without pulling toward any distribution
+            "--no-freq-correction" => settings.freq_correction = false,
+            // the same seed with the same input, settings and size always gives the same map; "time" picks one from the clock
+            "--seed" =>
+            {
+                i += 1;
+                seed = match args.get(i).expect("--seed needs a number or \"time\"").as_str()
+                {
+                    "time" => std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_millis(),
+                    other => other.parse().unwrap_or_else(|_| panic!("bad seed {}", other))
+                };
+            }
+            // WxH of the output in tiles, counting the border ring
+            "--size" =>
+            {
+                i += 1;
+                let text = args.get(i).expect("--size needs WxH");
+                let (w, h) = text.split_once('x').unwrap_or_else(|| panic!("--size needs WxH, got {}", text));
+                size = Some((w.parse::<usize>().unwrap(), h.parse::<usize>().unwrap()));
+            }
+            // only write the final map, not the snapshots taken along the way and on failures
+            "--no-snapshots" => snapshots = false,
+            // make this many maps at once, with seeds derived from --seed, each named after its seed
+            "--batch" =>
+            {
+                i += 1;
+                batch = Some(args.get(i).expect("--batch needs a count").parse::<usize>().unwrap());
+            }
+            // how many of the batch's maps to make at the same time; defaults to one per core
+            "--batch-threads" =>
+            {
+                i += 1;
+                batch_threads = Some(args.get(i).expect("--batch-threads needs a count").parse::<usize>().unwrap());
+            }
+            // make a world COLSxROWS chunks big instead of one map, chunk by chunk, with --size being a chunk's size
+            // counting its border ring (which is shared with the neighboring chunks). chunks are made a row at a time
+            // from the top left and each fits the ones made before it, so a chunk only comes out the same with the
+            // same seed, --size and COLSxROWS; a chunk from a bigger or smaller world is a different one
+            "--chunks" =>
+            {
+                i += 1;
+                let text = args.get(i).expect("--chunks needs COLSxROWS");
+                let (cols, rows) = text.split_once('x').unwrap_or_else(|| panic!("--chunks needs COLSxROWS, got {}", text));
+                chunks = Some((cols.parse::<usize>().unwrap(), rows.parse::<usize>().unwrap()));
+            }
+            // grow an earlier map (its .csv from --grid) by --grow, keeping what's already there
+            "--extend" =>
+            {
+                i += 1;
+                extend = Some(args.get(i).expect("--extend needs the .csv of the map to extend").to_string());
+            }
+            // N or LEFT,TOP,RIGHT,BOTTOM cells to add to the map being extended
+            "--grow" =>
+            {
+                i += 1;
+                grow = Some(extend::Margins::parse(args.get(i).expect("--grow needs N or LEFT,TOP,RIGHT,BOTTOM")));
+            }
+            // make part of an earlier map (its .csv from --grid) again with this run's seed, keeping the rest as it was;
+            // the part is --region or --mask
+            "--reroll" =>
+            {
+                i += 1;
+                reroll = Some(args.get(i).expect("--reroll needs the .csv of the map to reroll").to_string());
+            }
+            // X,Y,WxH in cells, border ring included
+            "--region" =>
+            {
+                i += 1;
+                let text = args.get(i).expect("--region needs X,Y,WxH");
+                let parts = text.split([',', 'x']).map(|part| part.parse::<usize>().unwrap_or_else(|_| panic!("--region needs X,Y,WxH, got {}", text))).collect::<Vec<_>>();
+                assert!(parts.len() == 4, "--region needs X,Y,WxH, got {}", text);
+                region = Some((parts[0], parts[1], parts[2], parts[3]));
+            }
+            // an image with a pixel per cell; anything not black or transparent gets rerolled
+            "--mask" =>
+            {
+                i += 1;
+                mask = Some(args.get(i).expect("--mask needs an image").to_string());
+            }
+            // save the run's state to the output's .checkpoint every N cells, overwriting the last one
+            "--checkpoint-every" =>
+            {
+                i += 1;
+                checkpoint_every = Some(args.get(i).expect("--checkpoint-every needs a count").parse::<usize>().unwrap());
+            }
+            // pick up from a checkpoint instead of starting over (and instead of --seed); everything else has to be
+            // the same as for the run that saved it
+            "--resume" =>
+            {
+                i += 1;
+                resume = Some(args.get(i).expect("--resume needs a checkpoint").to_string());
+            }
+            // check the finished map against the example, writing _report.json and _report.png next to it
+            "--validate" => validate = true,
+            // only check a map (its .csv from --grid, maybe edited by hand) against the example, writing the report
+            // named after the output instead of generating anything
+            "--check" =>
+            {
+                i += 1;
+                check = Some(args.get(i).expect("--check needs the .csv of the map to check").to_string());
+            }
+            // measure how much the finished map (or the one given to --check) looks like the example, using NxN
+            // patterns, and write _stats.json next to it
+            "--stats" =>
+            {
+                i += 1;
+                stats = Some(args.get(i).expect("--stats needs a pattern size").parse::<usize>().unwrap());
+            }
+            // only write out the learned neighbor rules (_rules.dot, _rules.json and a _rules.png contact sheet) named
+            // after the output, instead of generating anything
+            "--rules" => write_rules = true,
+            // only check the learned model (_lint.json, named after the output) instead of generating anything; the
+            // problems it finds get printed before every run anyway for tilesets of up to lint::AUTO_LINT_MAX_TILES
+            "--lint" => write_lint = true,
+            other => panic!("unknown argument: {}", other)
         }
+        i += 1;
     }
+    assert!(batch.is_none() || gif_delay.is_none(), "--gif doesn't work with --batch");
+    assert!(chunks.is_none() || gif_delay.is_none(), "--gif doesn't work with --chunks");
+    assert!(chunks.is_none() || batch.is_none(), "--chunks and --batch don't work together");
+    assert!(chunks.is_none() || !write_tiled, "--tiled doesn't work with --chunks");
+    assert!(extend.is_some() == grow.is_some(), "--extend and --grow go together");
+    assert!(extend.is_none() || (size.is_none() && chunks.is_none()), "an extended map's size comes from --grow");
+    assert!(reroll.is_none() || (region.is_some() != mask.is_some()), "--reroll needs either --region or --mask");
+    assert!(reroll.is_some() || (region.is_none() && mask.is_none()), "--region and --mask only go with --reroll");
+    assert!(reroll.is_none() || (size.is_none() && chunks.is_none() && extend.is_none()), "a rerolled map keeps its size");
+    assert!((checkpoint_every.is_none() && resume.is_none()) || (batch.is_none() && chunks.is_none()), "checkpoints don't work with --batch or --chunks");
+    assert!(checkpoint_every != Some(0), "--checkpoint-every needs at least 1");
     
-    let mut dead_tiles = Vec::new();
-    for y in 0..collapser.height as isize
+    assert!(extra_layers.is_empty() || !tiled::is_tiled_map(&args[1]), "--layer only goes with png inputs; every tile layer of a tiled map is read from the map itself");
+    let inputs = if tiled::is_tiled_map(&args[1])
+    {
+        // learn from the tile gids in the map directly instead of from its pixels
+        let map = tiled::load_map(&args[1]);
+        (0..map.layers.len()).map(|i|
+        {
+            let (example, id_to_tile) = map.to_example(i);
+            (map.layers[i].name.clone(), example, id_to_tile)
+        }).collect()
+    }
+    else
     {
-        for x in 0..collapser.width as isize
+        std::iter::once(&args[1]).chain(extra_layers.iter()).map(|path|
         {
-            match collapser.get_type(x, y)
+            let px_map = load_px_map(std::path::Path::new(path));
+            let (width, height) = (px_map[0].len(), px_map.len());
+            let (example, id_to_tile) = dedupe_tiles(&px_map, width, height);
+            let name = std::path::Path::new(path).file_stem().unwrap().to_string_lossy().to_string();
+            (name, example, id_to_tile)
+        }).collect()
+    };
+    let mut collapser = Collapser::from_layers(inputs, args[2].to_string());
+    collapser.snapshots = snapshots;
+    if write_lint
+    {
+        let lint = collapser.write_lint(output_stem(&args[2]));
+        println!("the tileset has {} problems", lint.issues.len());
+        return;
+    }
+    // the dead end check goes over every tile with every pair of its neighbors, so only small tilesets get it for free
+    if collapser.max_index <= lint::AUTO_LINT_MAX_TILES
+    {
+        collapser.lint().print_summary();
+    }
+    if write_rules
+    {
+        collapser.write_rules(output_stem(&args[2]));
+        println!("wrote the neighbor rules of {} tiles", collapser.max_index);
+        return;
+    }
+    if let Some(path) = &check
+    {
+        let (types, width, height) = export::parse_grid_csv(&std::fs::read_to_string(path).unwrap());
+        let report = collapser.write_report(output_stem(&args[2]), &types, width, height);
+        if let Some(n) = stats
+        {
+            collapser.write_stats(output_stem(&args[2]), &types, width, height, n);
+        }
+        println!("{} is {}", path, if report.is_valid() { "valid" } else { "not valid" });
+        return;
+    }
+    if let Some(delay) = gif_delay
+    {
+        let path = format!("{}.gif", output_stem(&args[2]));
+        println!("recording animation to {}", path);
+        collapser.record_gif(&path, delay);
+    }
+    collapser.apply_settings(settings);
+    // (re)made after the settings, since which solver they pick decides what gets allocated
+    if let (Some(path), Some(margins)) = (&extend, grow)
+    {
+        let (old, old_width, old_height) = export::parse_grid_csv(&std::fs::read_to_string(path).unwrap());
+        collapser.extend_from(&old, old_width, old_height, margins);
+    }
+    else if let Some(path) = &reroll
+    {
+        let (old, width, height) = export::parse_grid_csv(&std::fs::read_to_string(path).unwrap());
+        let mask = match (region, &mask)
+        {
+            (Some(rect), _) => extend::rect_mask(width, height, rect),
+            (_, Some(mask_path)) => extend::load_mask(mask_path, width, height),
+            _ => unreachable!()
+        };
+        collapser.reroll_from(&old, width, height, &mask);
+    }
+    else
+    {
+        let (width, height) = size.unwrap_or((collapser.width, collapser.height));
+        collapser.set_output_size(width, height);
+    }
+    for constraint in count_constraints
+    {
+        collapser.add_count_constraint(constraint);
+    }
+    if !walkable.is_empty()
+    {
+        collapser.set_walkable(&walkable);
+    }
+    for cell in required_walkable
+    {
+        collapser.require_walkable(cell);
+    }
+    if let Some(count) = batch
+    {
+        let seeds = batch::derive_seeds(seed, count);
+        let threads = batch_threads.unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1));
+        println!("making {} maps from seed {} on {} threads", count, seed, threads);
+        let outcomes = batch::run_batch(&collapser, output_stem(&args[2]), &seeds, threads, write_tiled, write_grid);
+        let summary_path = format!("{}_summary.csv", output_stem(&args[2]));
+        batch::write_summary(&summary_path, &outcomes);
+        for outcome in outcomes.iter()
+        {
+            println!("{}: {} dead cells", outcome.path, outcome.dead_cells);
+        }
+        if let Some(best) = outcomes.iter().min_by_key(|outcome| outcome.dead_cells)
+        {
+            println!("fewest dead cells: {} ({})", best.path, best.dead_cells);
+        }
+        println!("wrote summary to {}", summary_path);
+        return;
+    }
+    if let Some((cols, rows)) = chunks
+    {
+        let stem = output_stem(&args[2]).to_string();
+        let mut world = world::World::new(collapser, seed);
+        for cy in 0..rows as isize
+        {
+            for cx in 0..cols as isize
             {
-                TileType::Dead =>
-                    dead_tiles.push((x, y)),
-                TileType::Field =>
-                    panic!("oops! an undecided tile was let through to the end of the algorithm! this indicates a bug with propagating probabilities"),
-                _ => continue
+                world.generate((cx, cy));
             }
         }
+        let (types, width, height) = world.stitch((0, 0), (cols, rows));
+        let dead = types.iter().filter(|t| **t == TileType::Dead).count();
+        if dead > 0
+        {
+            println!("!!!!---- {} dead cells across the world", dead);
+        }
+        export::render_grid(&types, width, height, world.id_to_tile()).save(&args[2]).unwrap();
+        if write_grid
+        {
+            let atlas_path = format!("{}_atlas.png", stem);
+            export::write_atlas(&atlas_path, world.id_to_tile());
+            export::write_grid_csv(&format!("{}.csv", stem), &types, width, height);
+            export::write_grid_json(&format!("{}.json", stem), &types, width, height, &atlas_path, world.tile_count());
+        }
+        println!("wrote a {}x{} world of {}x{} chunks to {}", width, height, cols, rows, args[2]);
+        return;
     }
+    match &resume
+    {
+        Some(path) => println!("resuming from {}", path),
+        None => println!("seed: {}", seed),
+    }
+    collapser.run_checkpointed(seed, resume.as_deref(), &format!("{}.checkpoint", output_stem(&args[2])), checkpoint_every);
+    
+    let dead_tiles = collapser.dead_cells();
+    
+    collapser.check_count_constraints();
+    collapser.check_connectivity();
     
     if !dead_tiles.is_empty()
     {
@@ -1124,7 +1987,19 @@ fn main() {
     }
     */
     
-    collapser.write_image(&"".to_string(), (-1, -1));
+    collapser.write_outputs(output_stem(&args[2]), write_tiled, write_grid);
+    if validate
+    {
+        collapser.write_report(output_stem(&args[2]), &collapser.out_map_types, collapser.width, collapser.height);
+    }
+    if let Some(n) = stats
+    {
+        collapser.write_stats(output_stem(&args[2]), &collapser.out_map_types, collapser.width, collapser.height, n);
+    }
+    if let Some(recorder) = &collapser.recorder
+    {
+        println!("wrote {} animation frames", recorder.frame_count());
+    }
 }
 
 
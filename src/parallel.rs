@@ -0,0 +1,129 @@
+use crate::{Collapser, DIRECTIONS};
+use std::sync::{mpsc, Arc, RwLock};
+
+// the default for Settings::min_parallel_work: below about this much work (cells times tiles squared, which is
+// roughly what recalculating them costs) a wave is worked out on the calling thread, since handing it to the workers
+// would cost more than it saves
+pub(crate) const MIN_PARALLEL_WORK : usize = 1 << 18;
+
+impl Collapser
+{
+    // recalculate_all a wave at a time: every damaged cell gets worked out from the fields as they were when its wave
+    // started, spread over the threads, and then the results are stored in cell order, which makes up the next wave's
+    // damage. so the outcome depends on the damage alone and not on the thread count or scheduling. it's a different
+    // order from the serial one though (each wave only sees the last one's results), so it makes different maps.
+    // the workers are started on the first wave big enough to need them and kept until the damage runs out; they
+    // read the collapser between waves while this thread only writes to it in between
+    pub(crate) fn recalculate_all_waves (
+        &mut self,
+        collapse_iteration : usize,
+        fail_early : bool,
+        retry_count : usize,
+        ) -> usize
+    {
+        let stride = DIRECTIONS.len()*self.max_index;
+        let work_per_cell = self.max_index*self.max_index;
+        let threads = self.settings.threads;
+        let min_work = self.settings.min_parallel_work;
+        let this = RwLock::new(self);
+        let this = &this;
+        let mut i = 0;
+        let mut i2 = 0;
+        let mut max_failstate = 0;
+        std::thread::scope(|scope|
+        {
+            let (done_send, done_receive) = mpsc::channel();
+            let mut workers = Vec::new();
+            loop
+            {
+                let wave = {
+                    let mut collapser = this.write().unwrap();
+                    let (width, height) = (collapser.width as isize, collapser.height as isize);
+                    let mut wave = collapser.damage.drain(..).map(|(x, y)| (x.rem_euclid(width), y.rem_euclid(height))).collect::<Vec<_>>();
+                    wave.sort_by_key(|(x, y)| (*y, *x));
+                    wave.dedup();
+                    Arc::new(wave)
+                };
+                if wave.is_empty()
+                {
+                    println!("recalculated {} tiles", i);
+                    return max_failstate;
+                }
+
+                let mut fields = vec!(0.0; wave.len()*stride);
+                let mut outcomes = vec!(None; wave.len());
+                if wave.len()*work_per_cell < min_work
+                {
+                    let collapser = this.read().unwrap();
+                    for (k, cell) in wave.iter().enumerate()
+                    {
+                        outcomes[k] = collapser.recalculate_fields(&mut fields[k*stride..(k+1)*stride], *cell);
+                    }
+                }
+                else
+                {
+                    if workers.is_empty()
+                    {
+                        for _ in 0..threads
+                        {
+                            let (job_send, job_receive) = mpsc::channel::<(Arc<Vec<(isize, isize)>>, usize, usize)>();
+                            let done_send = done_send.clone();
+                            scope.spawn(move ||
+                            {
+                                for (wave, start, end) in job_receive
+                                {
+                                    let mut fields = vec!(0.0; (end - start)*stride);
+                                    let collapser = this.read().unwrap();
+                                    let outcomes = wave[start..end].iter().enumerate()
+                                        .map(|(k, cell)| collapser.recalculate_fields(&mut fields[k*stride..(k+1)*stride], *cell))
+                                        .collect::<Vec<_>>();
+                                    drop(collapser);
+                                    done_send.send((start, fields, outcomes)).unwrap();
+                                }
+                            });
+                            workers.push(job_send);
+                        }
+                    }
+                    let chunk = wave.len().div_ceil(threads);
+                    let mut jobs = 0;
+                    for (start, worker) in (0..wave.len()).step_by(chunk).zip(&workers)
+                    {
+                        worker.send((wave.clone(), start, std::cmp::min(start + chunk, wave.len()))).unwrap();
+                        jobs += 1;
+                    }
+                    for _ in 0..jobs
+                    {
+                        let (start, chunk_fields, chunk_outcomes) = done_receive.recv().unwrap();
+                        fields[start*stride..start*stride + chunk_fields.len()].copy_from_slice(&chunk_fields);
+                        outcomes[start..start + chunk_outcomes.len()].copy_from_slice(&chunk_outcomes);
+                    }
+                }
+
+                let mut collapser = this.write().unwrap();
+                for (k, cell) in wave.iter().enumerate()
+                {
+                    let failstate = match outcomes[k]
+                    {
+                        Some(outcome) => collapser.store_recalculation(&fields[k*stride..(k+1)*stride], *cell, outcome),
+                        None => 0
+                    };
+                    max_failstate = std::cmp::max(max_failstate, failstate);
+                    if failstate > 0
+                    {
+                        i += 1;
+                    }
+                    if failstate > 2
+                    {
+                        i2 += 1;
+                        collapser.write_image(format!("{}-{}-b{}", collapse_iteration, retry_count, i2), *cell);
+                        if fail_early
+                        {
+                            println!("recalculated {} tiles and short circuited", i);
+                            return failstate;
+                        }
+                    }
+                }
+            }
+        })
+    }
+}
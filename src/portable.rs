@@ -0,0 +1,101 @@
+// the float functions the solver needs beyond + - * / and sqrt, built out of just those so they round the same way
+// everywhere; std's powf, exp and ln call into the platform's libm, which is free to differ in the last bits, and one
+// bit is enough to flip a weighted pick and change the whole map from there on
+
+const LN_2 : f64 = std::f64::consts::LN_2;
+
+// multiplies by 2^n without going through powi
+fn scale(mut x : f64, mut n : i64) -> f64
+{
+    while n > 1023
+    {
+        x *= f64::from_bits(0x7fe << 52);
+        n -= 1023;
+    }
+    while n < -1022
+    {
+        x *= f64::MIN_POSITIVE;
+        n += 1022;
+    }
+    x * f64::from_bits(((n + 1023) as u64) << 52)
+}
+
+// natural log of a positive, finite number
+pub fn ln(x : f64) -> f64
+{
+    assert!(x > 0.0 && x.is_finite(), "ln of {}", x);
+    let mut x = x;
+    let mut e = 0;
+    if x < f64::MIN_POSITIVE
+    {
+        // subnormal, bring it into the normal range first
+        x *= f64::from_bits((1023 + 54) << 52);
+        e -= 54;
+    }
+    // x = m * 2^e with m in [sqrt(1/2), sqrt(2))
+    let bits = x.to_bits();
+    e += ((bits >> 52) & 0x7ff) as i64 - 1023;
+    let mut m = f64::from_bits((bits & 0x000f_ffff_ffff_ffff) | (1023 << 52));
+    if m > std::f64::consts::SQRT_2
+    {
+        m /= 2.0;
+        e += 1;
+    }
+    // ln(m) = 2*atanh(s) = 2*(s + s^3/3 + s^5/5 + ...), |s| < 0.172
+    let s = (m - 1.0)/(m + 1.0);
+    let s2 = s*s;
+    let mut term = s;
+    let mut sum = 0.0;
+    for k in 0..12
+    {
+        sum += term/(2*k + 1) as f64;
+        term *= s2;
+    }
+    2.0*sum + e as f64*LN_2
+}
+
+pub fn exp(x : f64) -> f64
+{
+    assert!(!x.is_nan(), "exp of NaN");
+    if x > 709.8
+    {
+        return f64::INFINITY;
+    }
+    if x < -745.2
+    {
+        return 0.0;
+    }
+    // x = n*ln(2) + r with |r| <= ln(2)/2
+    let n = (x/LN_2).round();
+    let r = x - n*LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    for k in 1..20
+    {
+        term *= r/k as f64;
+        sum += term;
+    }
+    scale(sum, n as i64)
+}
+
+// x^y for x >= 0
+pub fn powf(x : f64, y : f64) -> f64
+{
+    assert!(x >= 0.0, "powf of negative {}", x);
+    if y == 0.0
+    {
+        1.0
+    }
+    else if y == 1.0
+    {
+        x
+    }
+    else if x == 0.0
+    {
+        if y > 0.0 { 0.0 } else { f64::INFINITY }
+    }
+    else
+    {
+        exp(y*ln(x))
+    }
+}
@@ -0,0 +1,64 @@
+use std::convert::TryInto;
+
+// everything random the solver does goes through this, so a game can drive generation from its own rng stream
+// (and tests can script the choices). Collapser::run uses oorandom's Rand64; run_with takes any Rng
+pub trait Rng
+{
+    // uniformly distributed in the range, which is never empty
+    fn rand_range(&mut self, range : std::ops::Range<u64>) -> u64;
+    // uniformly distributed in [0, 1)
+    fn rand_float(&mut self) -> f64;
+    // the whole state, for checkpoints; restore_state gets it back from what save_state gave. rngs that are never
+    // checkpointed don't need them
+    fn save_state(&self) -> Vec<u8>
+    {
+        panic!("this rng can't be checkpointed")
+    }
+    fn restore_state(&mut self, _state : &[u8])
+    {
+        panic!("this rng can't be checkpointed")
+    }
+}
+
+impl Rng for oorandom::Rand64
+{
+    fn rand_range(&mut self, range : std::ops::Range<u64>) -> u64
+    {
+        oorandom::Rand64::rand_range(self, range)
+    }
+    fn rand_float(&mut self) -> f64
+    {
+        oorandom::Rand64::rand_float(self)
+    }
+    fn save_state(&self) -> Vec<u8>
+    {
+        let (state, inc) = self.state();
+        [state.to_le_bytes(), inc.to_le_bytes()].concat()
+    }
+    fn restore_state(&mut self, state : &[u8])
+    {
+        assert!(state.len() == 32, "a Rand64 state is 32 bytes, not {}", state.len());
+        let (state, inc) = state.split_at(16);
+        *self = oorandom::Rand64::from_state((u128::from_le_bytes(state.try_into().unwrap()), u128::from_le_bytes(inc.try_into().unwrap())));
+    }
+}
+
+impl<R : Rng + ?Sized> Rng for &mut R
+{
+    fn rand_range(&mut self, range : std::ops::Range<u64>) -> u64
+    {
+        (**self).rand_range(range)
+    }
+    fn rand_float(&mut self) -> f64
+    {
+        (**self).rand_float()
+    }
+    fn save_state(&self) -> Vec<u8>
+    {
+        (**self).save_state()
+    }
+    fn restore_state(&mut self, state : &[u8])
+    {
+        (**self).restore_state(state)
+    }
+}
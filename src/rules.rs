@@ -0,0 +1,149 @@
+use crate::{json, Collapser, TileId, DIRECTIONS, TILESIZE};
+use std::fmt::Write;
+
+// the learned neighbor rules in forms that can be looked at: a graphviz graph, json, and a contact sheet. weights
+// are what ships holds, normalized per tile and direction (ships leaves the forbidden tile's raw counts as they are)
+
+// gaps between things on the contact sheet, and the height of the weight bars under neighbors
+const GAP : usize = 2;
+const SECTION_GAP : usize = 6;
+const BAR_HEIGHT : usize = 3;
+const BACKGROUND : image::Rgba<u8> = image::Rgba([32, 32, 32, 255]);
+const BAR_COLOR : image::Rgba<u8> = image::Rgba([255, 220, 0, 255]);
+const EDGE_COLOR : image::Rgba<u8> = image::Rgba([255, 64, 64, 255]);
+
+pub fn direction_name(dir : usize) -> String
+{
+    match DIRECTIONS[dir]
+    {
+        (1, 0) => "right".to_string(),
+        (0, 1) => "down".to_string(),
+        (-1, 0) => "left".to_string(),
+        (0, -1) => "up".to_string(),
+        (x, y) => format!("{},{}", x, y),
+    }
+}
+
+impl Collapser
+{
+    // the tiles that may sit in direction dir of a, with their weights, most likely first
+    pub fn neighbor_rules(&self, a : TileId, dir : usize) -> Vec<(TileId, f64)>
+    {
+        let total = (0..self.max_index).map(|b| self.edge_weight(a, b, dir)).sum::<f64>();
+        let mut rules = (0..self.max_index).filter(|b| self.edge_weight(a, *b, dir) != 0.0).map(|b| (b, self.edge_weight(a, b, dir)/total)).collect::<Vec<_>>();
+        rules.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap().then(x.0.cmp(&y.0)));
+        rules
+    }
+    // an edge from a to b for every tile b that may neighbor a, labeled with the directions and weights
+    pub fn rules_dot(&self) -> String
+    {
+        let mut out = String::new();
+        writeln!(out, "digraph rules {{").unwrap();
+        writeln!(out, "    node [shape=box];").unwrap();
+        for a in 0..self.max_index
+        {
+            let forbidden = if self.forbidden_tiles.contains(&a) { ", style=dashed" } else { "" };
+            writeln!(out, "    t{} [label=\"tile {}\\n{}x\"{}];", a, a, self.freqs[a], forbidden).unwrap();
+        }
+        for a in 0..self.max_index
+        {
+            let mut labels = vec!(Vec::new(); self.max_index);
+            for dir in 0..DIRECTIONS.len()
+            {
+                for (b, weight) in self.neighbor_rules(a, dir)
+                {
+                    labels[b].push(format!("{} {:.3}", direction_name(dir), weight));
+                }
+            }
+            for (b, labels) in labels.iter().enumerate().filter(|(_, labels)| !labels.is_empty())
+            {
+                writeln!(out, "    t{} -> t{} [label=\"{}\"];", a, b, labels.join("\\n")).unwrap();
+            }
+        }
+        writeln!(out, "}}").unwrap();
+        out
+    }
+    pub fn rules_json(&self) -> String
+    {
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"tile_count\": {},", self.max_index).unwrap();
+        writeln!(out, "  \"tile_size\": {},", TILESIZE).unwrap();
+        let directions = (0..DIRECTIONS.len()).map(|dir| format!("{{\"name\": \"{}\", \"offset\": [{}, {}]}}", json::escape(&direction_name(dir)), DIRECTIONS[dir].0, DIRECTIONS[dir].1)).collect::<Vec<_>>();
+        writeln!(out, "  \"directions\": [{}],", directions.join(", ")).unwrap();
+        writeln!(out, "  \"tiles\": [").unwrap();
+        for a in 0..self.max_index
+        {
+            let neighbors = (0..DIRECTIONS.len()).map(|dir|
+            {
+                let rules = self.neighbor_rules(a, dir).iter().map(|(b, weight)| format!("{{\"tile\": {}, \"weight\": {}}}", b, weight)).collect::<Vec<_>>();
+                format!("\"{}\": [{}]", json::escape(&direction_name(dir)), rules.join(", "))
+            }).collect::<Vec<_>>();
+            writeln!(out, "    {{\"id\": {}, \"frequency\": {}, \"forbidden\": {}, \"neighbors\": {{{}}}}}{}",
+                a, self.freqs[a], self.forbidden_tiles.contains(&a), neighbors.join(", "), if a + 1 < self.max_index { "," } else { "" }).unwrap();
+        }
+        writeln!(out, "  ]").unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+    fn draw_tile(&self, image : &mut image::RgbaImage, id : TileId, (x, y) : (usize, usize))
+    {
+        for ty in 0..TILESIZE
+        {
+            for tx in 0..TILESIZE
+            {
+                let px = &self.id_to_tile[id][ty*TILESIZE + tx];
+                image.put_pixel((x + tx) as u32, (y + ty) as u32, image::Rgba([px.r, px.g, px.b, px.a]));
+            }
+        }
+    }
+    // a row per tile, with a section per direction: the tile with that side marked red, then every tile that may sit
+    // on that side of it, most likely first, each with a bar under it as long as its weight
+    pub fn rules_contact_sheet(&self) -> image::RgbaImage
+    {
+        let rules = (0..self.max_index).map(|a| (0..DIRECTIONS.len()).map(|dir| self.neighbor_rules(a, dir)).collect::<Vec<_>>()).collect::<Vec<_>>();
+        let step = TILESIZE + GAP;
+        let row_height = TILESIZE + GAP + BAR_HEIGHT + SECTION_GAP;
+        let width = rules.iter().map(|sections| sections.iter().map(|s| (s.len() + 1)*step + SECTION_GAP).sum::<usize>()).max().unwrap_or(0) + SECTION_GAP;
+        let mut image = image::RgbaImage::from_pixel(width as u32, (self.max_index*row_height + SECTION_GAP) as u32, BACKGROUND);
+        for (a, sections) in rules.iter().enumerate()
+        {
+            let y = SECTION_GAP + a*row_height;
+            let mut x = SECTION_GAP;
+            for (dir, section) in sections.iter().enumerate()
+            {
+                self.draw_tile(&mut image, a, (x, y));
+                let (dx, dy) = DIRECTIONS[dir];
+                for i in 0..TILESIZE
+                {
+                    let tx = if dx > 0 { TILESIZE - 1 } else if dx < 0 { 0 } else { i };
+                    let ty = if dy > 0 { TILESIZE - 1 } else if dy < 0 { 0 } else { i };
+                    image.put_pixel((x + tx) as u32, (y + ty) as u32, EDGE_COLOR);
+                }
+                x += step;
+                for (b, weight) in section.iter()
+                {
+                    self.draw_tile(&mut image, *b, (x, y));
+                    let bar = std::cmp::max(1, (weight*TILESIZE as f64).round() as usize);
+                    for by in 0..BAR_HEIGHT
+                    {
+                        for bx in 0..bar
+                        {
+                            image.put_pixel((x + bx) as u32, (y + TILESIZE + GAP + by) as u32, BAR_COLOR);
+                        }
+                    }
+                    x += step;
+                }
+                x += SECTION_GAP;
+            }
+        }
+        image
+    }
+    // {stem}_rules.dot, {stem}_rules.json and {stem}_rules.png
+    pub fn write_rules(&self, stem : &str)
+    {
+        std::fs::write(format!("{}_rules.dot", stem), self.rules_dot()).unwrap();
+        std::fs::write(format!("{}_rules.json", stem), self.rules_json()).unwrap();
+        self.rules_contact_sheet().save(format!("{}_rules.png", stem)).unwrap();
+    }
+}
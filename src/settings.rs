@@ -0,0 +1,248 @@
+use crate::TileId;
+
+// an override of how common a tile should be in the output
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum TargetFreq
+{
+    // this share of all output tiles, 0.0 to 1.0
+    Share(TileId, f64),
+    // this many times as common as in the example
+    Scale(TileId, f64),
+}
+
+impl TargetFreq
+{
+    // "id:share" or "id:xscale", e.g. "3:0.25" or "3:x2"
+    pub fn parse(text : &str) -> TargetFreq
+    {
+        let (id, value) = text.split_once(':').unwrap_or_else(|| panic!("target frequencies look like id:share or id:xscale, got {}", text));
+        let id = id.trim().parse().unwrap_or_else(|_| panic!("bad tile id in target frequency {}", text));
+        if let Some(scale) = value.trim().strip_prefix('x')
+        {
+            let scale = scale.parse().unwrap_or_else(|_| panic!("bad scale in target frequency {}", text));
+            assert!(scale >= 0.0, "target frequency scales can't be negative");
+            TargetFreq::Scale(id, scale)
+        }
+        else
+        {
+            let share = value.trim().parse().unwrap_or_else(|_| panic!("bad share in target frequency {}", text));
+            assert!((0.0..=1.0).contains(&share), "target frequency shares go from 0 to 1");
+            TargetFreq::Share(id, share)
+        }
+    }
+    pub fn tile(&self) -> TileId
+    {
+        match self
+        {
+            TargetFreq::Share(id, _) | TargetFreq::Scale(id, _) => *id,
+        }
+    }
+}
+
+// how a cell's tile gets picked out of its possibilities
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Sampling
+{
+    // proportionally to the tiles' weights, sharpened or flattened by the temperature
+    Weighted,
+    // any possible tile with equal chance, ignoring weights
+    Uniform,
+}
+
+// how possibilities are tracked while solving
+#[derive(Clone)]
+#[derive(Copy)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub enum Solver
+{
+    // a weight per tile per direction per cell, propagated with the chaos terms below
+    Fields,
+    // which tiles are still possible, one bit each, kept arc consistent with support counts; the fields are only
+    // worked out again around what changed, for sampling. faster for big tilesets, but ignores walkability
+    Bitset,
+}
+
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(PartialEq)]
+pub struct Settings
+{
+    pub solver : Solver,
+    // 1 propagates the fields one cell at a time like always; more recalculates whole waves of cells at once on that
+    // many threads. waves give different maps from 1 thread, but the same ones for any number above it
+    pub threads : usize,
+    // waves with less work than this (cells times tiles squared) are recalculated on one thread anyway
+    pub min_parallel_work : usize,
+    pub sampling : Sampling,
+    // weights are raised to 1/temperature before sampling: 1.0 leaves them alone, lower sharpens toward
+    // always taking the most likely tile (0.0 does exactly that), higher flattens toward uniform
+    pub temperature : f64,
+
+    // when a cell's field is updated from a neighbor that isn't decided yet, it becomes
+    //   chaos_floor + old*chaos_decay + modifier*chaos_blend
+    // instead of just the modifier (which is what decided neighbors give). this controls the overall
+    // amount of chaos in the system; leaning on the modifier is low-chaos, leaning on the old value is high-chaos
+    pub chaos_floor : f64,
+    pub chaos_decay : f64,
+    pub chaos_blend : f64,
+    // modifiers are clamped to at least this, to avoid making floats that risk becoming subnormal when
+    // multiplied across directions
+    pub min_modifier : f64,
+
+    // whether to keep nudging output tile frequencies toward the target distribution at all;
+    // without it tiles are picked purely by the local weights
+    pub freq_correction : bool,
+    // exponent on the target/actual frequency ratio; 1.0 is a plain ratio, higher corrects harder
+    pub freq_strength : f64,
+    // overrides of the target distribution, which is otherwise the example's
+    pub target_freqs : Vec<TargetFreq>,
+}
+
+impl Default for Settings
+{
+    fn default() -> Settings
+    {
+        Settings
+        {
+            solver : Solver::Fields,
+            threads : 1,
+            min_parallel_work : crate::parallel::MIN_PARALLEL_WORK,
+            sampling : Sampling::Weighted,
+            temperature : 1.0,
+            chaos_floor : 0.005,
+            chaos_decay : 0.975,
+            chaos_blend : 0.020,
+            min_modifier : 0.0001,
+            freq_correction : true,
+            freq_strength : 1.0,
+            target_freqs : Vec::new(),
+        }
+    }
+}
+
+// (name, floor, decay, blend)
+pub const PRESETS : [(&str, f64, f64, f64); 4] = [
+    ("default", 0.005, 0.975, 0.020),
+    // only ever listen to the neighbors
+    ("strict",  0.0,   0.0,   1.0),
+    ("chaotic", 0.005, 0.985, 0.010),
+    ("noisy",   0.05,  0.9,   0.05),
+];
+
+fn parse_value<T : std::str::FromStr>(key : &str, value : &str) -> T
+{
+    value.trim().parse().unwrap_or_else(|_| panic!("bad value for {}: {}", key, value))
+}
+
+// the chaos terms and exponents multiply into the fields, which have to stay finite and not negative
+fn parse_non_negative(key : &str, value : &str) -> f64
+{
+    let value : f64 = parse_value(key, value);
+    assert!(value.is_finite() && value >= 0.0, "{} has to be a number of at least 0, got {}", key, value);
+    value
+}
+
+impl Settings
+{
+    pub fn apply_preset(&mut self, name : &str)
+    {
+        let (_, floor, decay, blend) = *PRESETS.iter().find(|p| p.0 == name).unwrap_or_else(||
+            panic!("unknown preset {}, the presets are: {}", name, PRESETS.iter().map(|p| p.0).collect::<Vec<_>>().join(", ")));
+        self.chaos_floor = floor;
+        self.chaos_decay = decay;
+        self.chaos_blend = blend;
+    }
+    // the same keys work in config files and (with dashes instead of underscores) on the command line
+    pub fn set(&mut self, key : &str, value : &str)
+    {
+        match key
+        {
+            "solver" => self.solver = match value.trim()
+            {
+                "fields" => Solver::Fields,
+                "bitset" => Solver::Bitset,
+                other => panic!("unknown solver {}, expected fields or bitset", other)
+            },
+            "threads" =>
+            {
+                self.threads = parse_value(key, value);
+                assert!(self.threads > 0, "threads has to be at least 1");
+            }
+            "min_parallel_work" => self.min_parallel_work = parse_value(key, value),
+            "sampling" => self.sampling = match value.trim()
+            {
+                "weighted" => Sampling::Weighted,
+                "uniform" => Sampling::Uniform,
+                other => panic!("unknown sampling mode {}, expected weighted or uniform", other)
+            },
+            "temperature" =>
+            {
+                self.temperature = parse_value(key, value);
+                assert!(self.temperature >= 0.0, "temperature can't be negative");
+            }
+            "preset" => self.apply_preset(value.trim()),
+            "chaos_floor" => self.chaos_floor = parse_non_negative(key, value),
+            "chaos_decay" => self.chaos_decay = parse_non_negative(key, value),
+            "chaos_blend" => self.chaos_blend = parse_non_negative(key, value),
+            "min_modifier" => self.min_modifier = parse_non_negative(key, value),
+            "freq_correction" => self.freq_correction = parse_value(key, value),
+            "freq_strength" => self.freq_strength = parse_non_negative(key, value),
+            "target" => self.target_freqs.push(TargetFreq::parse(value)),
+            _ => panic!("unknown setting {}", key)
+        }
+    }
+    // "key = value" lines, applied in order; blank lines and lines starting with # are skipped
+    pub fn load_config(&mut self, path : &str)
+    {
+        let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read config {}: {}", path, e));
+        for (i, line) in text.lines().enumerate()
+        {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#')
+            {
+                continue;
+            }
+            let (key, value) = line.split_once('=').unwrap_or_else(|| panic!("{}:{}: expected key = value", path, i+1));
+            self.set(key.trim(), value);
+        }
+    }
+}
+
+// the example's tile frequencies with the given overrides applied, normalized to sum to 1
+pub fn target_distribution(freqs : &[f64], total_freq : f64, overrides : &[TargetFreq]) -> Vec<f64>
+{
+    // kept exactly as it always was when nothing is overridden
+    let mut target = freqs.iter().map(|f| f/total_freq).collect::<Vec<_>>();
+    if overrides.is_empty()
+    {
+        return target;
+    }
+    let mut fixed = vec!(false; freqs.len());
+    for o in overrides
+    {
+        assert!(o.tile() < freqs.len(), "target frequency for tile {}, but there are only {} tiles", o.tile(), freqs.len());
+        match *o
+        {
+            TargetFreq::Scale(id, scale) => target[id] *= scale,
+            TargetFreq::Share(id, share) => { target[id] = share; fixed[id] = true; }
+        }
+    }
+    let fixed_total : f64 = (0..freqs.len()).filter(|i| fixed[*i]).map(|i| target[i]).sum();
+    assert!(fixed_total <= 1.0, "target frequency shares add up to more than 1");
+    let free_total : f64 = (0..freqs.len()).filter(|i| !fixed[*i]).map(|i| target[i]).sum();
+    for i in 0..freqs.len()
+    {
+        if !fixed[i] && free_total > 0.0
+        {
+            target[i] *= (1.0 - fixed_total)/free_total;
+        }
+    }
+    target
+}
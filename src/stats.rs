@@ -0,0 +1,187 @@
+use crate::{Collapser, TileId, TileType, DIRECTIONS};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+// how much a finished map looks like the example it was learned from, for comparing settings against each other.
+// the border tile is left out of the tile and neighbor counts, since how much of it there is only depends on the sizes
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Stats
+{
+    // per tile: its share of the example and of the output
+    pub tile_shares : Vec<(f64, f64)>,
+    // jensen-shannon divergence between those, in bits: 0 for the same distribution, 1 for nothing in common
+    pub frequency_divergence : f64,
+    // the same for neighbor pairs (in each direction), against the pairs the neighbor weights were learned from
+    pub adjacency_divergence : f64,
+    pub pattern_size : usize,
+    // distinct NxN patterns in the example, and how many of them show up in the output
+    pub example_patterns : usize,
+    pub covered_patterns : usize,
+    // NxN windows of the output (without dead cells), and how many of them aren't anywhere in the example
+    pub output_windows : usize,
+    pub novel_windows : usize,
+}
+
+// in bits; both are normalized here, and empty ones count as identical to anything
+fn js_divergence(p : &[f64], q : &[f64]) -> f64
+{
+    let (p_total, q_total) = (p.iter().sum::<f64>(), q.iter().sum::<f64>());
+    if p_total == 0.0 || q_total == 0.0
+    {
+        return 0.0;
+    }
+    let mut divergence = 0.0;
+    for (p, q) in p.iter().zip(q.iter())
+    {
+        let (p, q) = (p/p_total, q/q_total);
+        let m = (p + q)/2.0;
+        if p > 0.0
+        {
+            divergence += p*(p/m).log2()/2.0;
+        }
+        if q > 0.0
+        {
+            divergence += q*(q/m).log2()/2.0;
+        }
+    }
+    divergence
+}
+
+impl Stats
+{
+    pub fn coverage(&self) -> f64
+    {
+        if self.example_patterns == 0 { 0.0 } else { self.covered_patterns as f64/self.example_patterns as f64 }
+    }
+    pub fn novelty(&self) -> f64
+    {
+        if self.output_windows == 0 { 0.0 } else { self.novel_windows as f64/self.output_windows as f64 }
+    }
+    pub fn print_summary(&self)
+    {
+        println!("tile frequency divergence: {:.4}", self.frequency_divergence);
+        println!("neighbor pair divergence: {:.4}", self.adjacency_divergence);
+        println!("{0}x{0} patterns: {1} of the example's {2} used ({3:.1}%), {4} of {5} windows not from the example ({6:.1}%)",
+            self.pattern_size, self.covered_patterns, self.example_patterns, self.coverage()*100.0,
+            self.novel_windows, self.output_windows, self.novelty()*100.0);
+    }
+    pub fn to_json(&self) -> String
+    {
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"frequency_divergence\": {},", self.frequency_divergence).unwrap();
+        writeln!(out, "  \"adjacency_divergence\": {},", self.adjacency_divergence).unwrap();
+        writeln!(out, "  \"pattern_size\": {},", self.pattern_size).unwrap();
+        writeln!(out, "  \"example_patterns\": {},", self.example_patterns).unwrap();
+        writeln!(out, "  \"covered_patterns\": {},", self.covered_patterns).unwrap();
+        writeln!(out, "  \"coverage\": {},", self.coverage()).unwrap();
+        writeln!(out, "  \"output_windows\": {},", self.output_windows).unwrap();
+        writeln!(out, "  \"novel_windows\": {},", self.novel_windows).unwrap();
+        writeln!(out, "  \"novelty\": {},", self.novelty()).unwrap();
+        let shares = self.tile_shares.iter().map(|(example, output)| format!("[{}, {}]", example, output)).collect::<Vec<_>>();
+        writeln!(out, "  \"tile_shares\": [{}]", shares.join(", ")).unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl Collapser
+{
+    // every NxN window lying fully inside a row-major map, skipping ones with a cell that isn't a tile
+    fn patterns(cell : impl Fn(usize, usize) -> Option<TileId>, width : usize, height : usize, n : usize) -> Vec<Vec<TileId>>
+    {
+        let mut patterns = Vec::new();
+        for y in 0..(height + 1).saturating_sub(n)
+        {
+            for x in 0..(width + 1).saturating_sub(n)
+            {
+                let pattern = (0..n*n).map(|i| cell(x + i%n, y + i/n)).collect::<Option<Vec<_>>>();
+                patterns.extend(pattern);
+            }
+        }
+        patterns
+    }
+    // compares a finished map (border ring included) against the example; n is the pattern size
+    pub fn stats(&self, types : &[TileType], width : usize, height : usize, n : usize) -> Stats
+    {
+        assert!(n >= 1, "patterns have to be at least 1x1");
+        let counted = |id : TileId| !self.forbidden_tiles.contains(&id);
+        let tile = |types : &[TileType], x : usize, y : usize| match types[y*width + x]
+        {
+            TileType::Tile(id) if id < self.max_index => Some(id),
+            _ => None
+        };
+        let (example_width, example_height) = (self.example[0].len(), self.example.len());
+
+        let example_counts = (0..self.max_index).map(|id| if counted(id) { self.freqs[id] } else { 0.0 }).collect::<Vec<_>>();
+        let mut output_counts = vec!(0.0; self.max_index);
+        // pairs[(a*max_index + b)*directions + dir], counted the same way the neighbor weights were: wrapping around
+        let mut example_pairs = vec!(0.0; self.max_index*self.max_index*DIRECTIONS.len());
+        let mut output_pairs = example_pairs.clone();
+        let pair = |a : TileId, b : TileId, dir : usize| (a*self.max_index + b)*DIRECTIONS.len() + dir;
+        for y in 0..example_height
+        {
+            for x in 0..example_width
+            {
+                let a = self.example[y][x];
+                if !counted(a)
+                {
+                    continue;
+                }
+                for (dir, offset) in DIRECTIONS.iter().enumerate()
+                {
+                    let b = self.example[(y as isize + offset.1).rem_euclid(example_height as isize) as usize][(x as isize + offset.0).rem_euclid(example_width as isize) as usize];
+                    if counted(b)
+                    {
+                        example_pairs[pair(a, b, dir)] += 1.0;
+                    }
+                }
+            }
+        }
+        for y in 0..height
+        {
+            for x in 0..width
+            {
+                let Some(a) = tile(types, x, y).filter(|a| counted(*a)) else { continue };
+                output_counts[a] += 1.0;
+                for (dir, offset) in DIRECTIONS.iter().enumerate()
+                {
+                    let (nx, ny) = ((x as isize + offset.0).rem_euclid(width as isize) as usize, (y as isize + offset.1).rem_euclid(height as isize) as usize);
+                    if let Some(b) = tile(types, nx, ny).filter(|b| counted(*b))
+                    {
+                        output_pairs[pair(a, b, dir)] += 1.0;
+                    }
+                }
+            }
+        }
+        let (example_total, output_total) = (example_counts.iter().sum::<f64>(), output_counts.iter().sum::<f64>());
+        let tile_shares = example_counts.iter().zip(output_counts.iter())
+            .map(|(e, o)| (if example_total > 0.0 { e/example_total } else { 0.0 }, if output_total > 0.0 { o/output_total } else { 0.0 }))
+            .collect();
+
+        let example_patterns = Collapser::patterns(|x, y| Some(self.example[y][x]), example_width, example_height, n).into_iter().collect::<HashSet<_>>();
+        let output_windows = Collapser::patterns(|x, y| tile(types, x, y), width, height, n);
+        let output_patterns = output_windows.iter().collect::<HashSet<_>>();
+
+        Stats
+        {
+            tile_shares,
+            frequency_divergence : js_divergence(&example_counts, &output_counts),
+            adjacency_divergence : js_divergence(&example_pairs, &output_pairs),
+            pattern_size : n,
+            example_patterns : example_patterns.len(),
+            covered_patterns : example_patterns.iter().filter(|pattern| output_patterns.contains(pattern)).count(),
+            output_windows : output_windows.len(),
+            novel_windows : output_windows.iter().filter(|pattern| !example_patterns.contains(*pattern)).count(),
+        }
+    }
+    // prints them and writes {stem}_stats.json
+    pub fn write_stats(&self, stem : &str, types : &[TileType], width : usize, height : usize, n : usize) -> Stats
+    {
+        let stats = self.stats(types, width, height, n);
+        stats.print_summary();
+        std::fs::write(format!("{}_stats.json", stem), stats.to_json()).unwrap();
+        stats
+    }
+}
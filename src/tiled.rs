@@ -0,0 +1,440 @@
+use crate::{load_px_map, json, xml, Rgba, TileId, TileType, TILESIZE};
+use crate::export::{atlas_columns, atlas_rows, file_name};
+use crate::layers::is_empty_tile;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const TILED_VERSION : &str = "1.10.2";
+const FORMAT_VERSION : &str = "1.10";
+
+// one tile layer of the written map, with its own tileset image (written separately, see export::write_atlas)
+pub struct MapLayer<'a>
+{
+    pub name : &'a str,
+    pub types : Vec<TileType>,
+    pub id_to_tile : &'a [Vec<Rgba>],
+    pub image_path : String,
+}
+
+// gid N+firstgid is TileId N of a layer; gid 0 is an empty cell
+fn gid(tile_type : TileType, firstgid : usize, id_to_tile : &[Vec<Rgba>]) -> usize
+{
+    match tile_type
+    {
+        // fully transparent tiles are how empty cells of upper layers come out of learning, so write them back as empty
+        TileType::Tile(id) if is_empty_tile(&id_to_tile[id]) => 0,
+        TileType::Tile(id) => id + firstgid,
+        // dead (and, if something went very wrong, undecided) cells are left empty so they're easy to find and paint over
+        _ => 0
+    }
+}
+
+fn firstgids(layers : &[MapLayer]) -> Vec<usize>
+{
+    let mut next = 1;
+    layers.iter().map(|layer| { next += layer.id_to_tile.len(); next - layer.id_to_tile.len() }).collect()
+}
+
+pub fn write_tmj(path : &str, width : usize, height : usize, layers : &[MapLayer])
+{
+    let firstgids = firstgids(layers);
+    let mut out = String::new();
+    writeln!(out, "{{").unwrap();
+    writeln!(out, "  \"type\": \"map\",").unwrap();
+    writeln!(out, "  \"version\": \"{}\",", FORMAT_VERSION).unwrap();
+    writeln!(out, "  \"tiledversion\": \"{}\",", TILED_VERSION).unwrap();
+    writeln!(out, "  \"orientation\": \"orthogonal\",").unwrap();
+    writeln!(out, "  \"renderorder\": \"right-down\",").unwrap();
+    writeln!(out, "  \"infinite\": false,").unwrap();
+    writeln!(out, "  \"compressionlevel\": -1,").unwrap();
+    writeln!(out, "  \"width\": {},", width).unwrap();
+    writeln!(out, "  \"height\": {},", height).unwrap();
+    writeln!(out, "  \"tilewidth\": {},", TILESIZE).unwrap();
+    writeln!(out, "  \"tileheight\": {},", TILESIZE).unwrap();
+    writeln!(out, "  \"nextlayerid\": {},", layers.len() + 1).unwrap();
+    writeln!(out, "  \"nextobjectid\": 1,").unwrap();
+    writeln!(out, "  \"tilesets\": [").unwrap();
+    for (i, layer) in layers.iter().enumerate()
+    {
+        let tile_count = layer.id_to_tile.len();
+        writeln!(out, "    {{").unwrap();
+        writeln!(out, "      \"firstgid\": {},", firstgids[i]).unwrap();
+        writeln!(out, "      \"name\": \"{}\",", json::escape(layer.name)).unwrap();
+        writeln!(out, "      \"image\": \"{}\",", json::escape(file_name(&layer.image_path))).unwrap();
+        writeln!(out, "      \"imagewidth\": {},", atlas_columns(tile_count)*TILESIZE).unwrap();
+        writeln!(out, "      \"imageheight\": {},", atlas_rows(tile_count)*TILESIZE).unwrap();
+        writeln!(out, "      \"tilewidth\": {},", TILESIZE).unwrap();
+        writeln!(out, "      \"tileheight\": {},", TILESIZE).unwrap();
+        writeln!(out, "      \"tilecount\": {},", tile_count).unwrap();
+        writeln!(out, "      \"columns\": {},", atlas_columns(tile_count)).unwrap();
+        writeln!(out, "      \"margin\": 0,").unwrap();
+        writeln!(out, "      \"spacing\": 0").unwrap();
+        writeln!(out, "    }}{}", if i+1 < layers.len() { "," } else { "" }).unwrap();
+    }
+    writeln!(out, "  ],").unwrap();
+    writeln!(out, "  \"layers\": [").unwrap();
+    for (i, layer) in layers.iter().enumerate()
+    {
+        let data = layer.types.iter().map(|t| gid(*t, firstgids[i], layer.id_to_tile).to_string()).collect::<Vec<_>>().join(", ");
+        writeln!(out, "    {{").unwrap();
+        writeln!(out, "      \"id\": {},", i + 1).unwrap();
+        writeln!(out, "      \"name\": \"{}\",", json::escape(layer.name)).unwrap();
+        writeln!(out, "      \"type\": \"tilelayer\",").unwrap();
+        writeln!(out, "      \"x\": 0,").unwrap();
+        writeln!(out, "      \"y\": 0,").unwrap();
+        writeln!(out, "      \"width\": {},", width).unwrap();
+        writeln!(out, "      \"height\": {},", height).unwrap();
+        writeln!(out, "      \"opacity\": 1,").unwrap();
+        writeln!(out, "      \"visible\": true,").unwrap();
+        writeln!(out, "      \"data\": [{}]", data).unwrap();
+        writeln!(out, "    }}{}", if i+1 < layers.len() { "," } else { "" }).unwrap();
+    }
+    writeln!(out, "  ]").unwrap();
+    writeln!(out, "}}").unwrap();
+    std::fs::write(path, out).unwrap();
+}
+
+pub fn write_tmx(path : &str, width : usize, height : usize, layers : &[MapLayer])
+{
+    let firstgids = firstgids(layers);
+    let mut out = String::new();
+    writeln!(out, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(out, "<map version=\"{}\" tiledversion=\"{}\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" nextlayerid=\"{}\" nextobjectid=\"1\">",
+        FORMAT_VERSION, TILED_VERSION, width, height, TILESIZE, TILESIZE, layers.len() + 1).unwrap();
+    for (i, layer) in layers.iter().enumerate()
+    {
+        let tile_count = layer.id_to_tile.len();
+        writeln!(out, " <tileset firstgid=\"{}\" name=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"{}\">",
+            firstgids[i], xml::escape(layer.name), TILESIZE, TILESIZE, tile_count, atlas_columns(tile_count)).unwrap();
+        writeln!(out, "  <image source=\"{}\" width=\"{}\" height=\"{}\"/>",
+            xml::escape(file_name(&layer.image_path)), atlas_columns(tile_count)*TILESIZE, atlas_rows(tile_count)*TILESIZE).unwrap();
+        writeln!(out, " </tileset>").unwrap();
+    }
+    for (i, layer) in layers.iter().enumerate()
+    {
+        writeln!(out, " <layer id=\"{}\" name=\"{}\" width=\"{}\" height=\"{}\">", i + 1, xml::escape(layer.name), width, height).unwrap();
+        writeln!(out, "  <data encoding=\"csv\">").unwrap();
+        for y in 0..height
+        {
+            let row = layer.types[y*width..(y+1)*width].iter().map(|t| gid(*t, firstgids[i], layer.id_to_tile).to_string()).collect::<Vec<_>>().join(",");
+            // every row but the last ends in a comma, that's how tiled writes it
+            writeln!(out, "{}{}", row, if y+1 < height { "," } else { "" }).unwrap();
+        }
+        writeln!(out, "</data>").unwrap();
+        writeln!(out, " </layer>").unwrap();
+    }
+    writeln!(out, "</map>").unwrap();
+    std::fs::write(path, out).unwrap();
+}
+
+// tiled stores flips/rotations in the top bits of each gid
+const FLIPPED_HORIZONTALLY : u32 = 0x8000_0000;
+const FLIPPED_VERTICALLY   : u32 = 0x4000_0000;
+const FLIPPED_DIAGONALLY   : u32 = 0x2000_0000;
+const ROTATED_HEXAGONAL    : u32 = 0x1000_0000;
+const FLAG_MASK : u32 = FLIPPED_HORIZONTALLY | FLIPPED_VERTICALLY | FLIPPED_DIAGONALLY | ROTATED_HEXAGONAL;
+
+struct TilesetImage
+{
+    firstgid : u32,
+    px_map : Vec<Vec<Rgba>>,
+    columns : usize,
+    tile_count : usize,
+    margin : usize,
+    spacing : usize,
+}
+
+pub struct TiledLayer
+{
+    pub name : String,
+    // row-major, with flip flags still attached
+    pub gids : Vec<u32>,
+}
+
+pub struct TiledMap
+{
+    pub width : usize,
+    pub height : usize,
+    pub layers : Vec<TiledLayer>,
+    tilesets : Vec<TilesetImage>,
+}
+
+fn relative_to(base_file : &Path, path : &str) -> PathBuf
+{
+    base_file.parent().map(|dir| dir.join(path)).unwrap_or_else(|| PathBuf::from(path))
+}
+
+fn decode_base64(text : &str) -> Vec<u8>
+{
+    fn value(c : u8) -> u32
+    {
+        match c
+        {
+            b'A'..=b'Z' => (c - b'A') as u32,
+            b'a'..=b'z' => (c - b'a') as u32 + 26,
+            b'0'..=b'9' => (c - b'0') as u32 + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => panic!("invalid base64 character {:?} in layer data", c as char)
+        }
+    }
+    let chars = text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=').collect::<Vec<_>>();
+    let mut out = Vec::with_capacity(chars.len()*3/4);
+    for chunk in chars.chunks(4)
+    {
+        let mut n = 0;
+        for (i, c) in chunk.iter().enumerate()
+        {
+            n |= value(*c) << (18 - 6*i);
+        }
+        for i in 0..chunk.len()-1
+        {
+            out.push((n >> (16 - 8*i)) as u8);
+        }
+    }
+    out
+}
+
+fn gids_from_base64(text : &str, compression : Option<&str>) -> Vec<u32>
+{
+    if let Some(compression) = compression.filter(|c| !c.is_empty())
+    {
+        panic!("{} compressed layer data isn't supported; set the layer format to CSV or uncompressed base64 in tiled", compression);
+    }
+    let bytes = decode_base64(text);
+    let gids = bytes.chunks_exact(4);
+    assert!(gids.remainder().is_empty(), "base64 layer data is {} bytes long, which isn't a whole number of 4 byte gids", bytes.len());
+    gids.map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect()
+}
+
+fn parse_gid(text : &str) -> u32
+{
+    text.trim().parse().unwrap_or_else(|_| panic!("bad gid {:?} in layer data", text))
+}
+
+fn gids_from_csv(text : &str) -> Vec<u32>
+{
+    text.split(',').map(|s| s.trim()).filter(|s| !s.is_empty()).map(parse_gid).collect()
+}
+
+// a numeric attribute of an element of the file at path
+fn attr_usize(element : &xml::Element, key : &str, path : &Path) -> Option<usize>
+{
+    element.attr_usize(key).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+fn tileset_from_xml(element : &xml::Element, firstgid : u32, base_file : &Path) -> TilesetImage
+{
+    let tile_width = attr_usize(element, "tilewidth", base_file).unwrap_or(TILESIZE);
+    let tile_height = attr_usize(element, "tileheight", base_file).unwrap_or(TILESIZE);
+    assert!(tile_width == TILESIZE && tile_height == TILESIZE, "tileset tiles must be {}x{}, got {}x{}", TILESIZE, TILESIZE, tile_width, tile_height);
+    let image = element.child("image").expect("only tilesets based on a single image are supported");
+    let px_map = load_px_map(&relative_to(base_file, image.attr("source").unwrap()));
+    let margin = attr_usize(element, "margin", base_file).unwrap_or(0);
+    let spacing = attr_usize(element, "spacing", base_file).unwrap_or(0);
+    let columns = attr_usize(element, "columns", base_file).unwrap_or((px_map[0].len() - margin*2 + spacing)/(TILESIZE + spacing));
+    let tile_count = attr_usize(element, "tilecount", base_file).unwrap_or(columns*((px_map.len() - margin*2 + spacing)/(TILESIZE + spacing)));
+    TilesetImage { firstgid, px_map, columns, tile_count, margin, spacing }
+}
+
+fn tileset_from_json(value : &json::Value, firstgid : u32, base_file : &Path) -> TilesetImage
+{
+    let tile_width = value.get("tilewidth").and_then(|v| v.as_usize()).unwrap_or(TILESIZE);
+    let tile_height = value.get("tileheight").and_then(|v| v.as_usize()).unwrap_or(TILESIZE);
+    assert!(tile_width == TILESIZE && tile_height == TILESIZE, "tileset tiles must be {}x{}, got {}x{}", TILESIZE, TILESIZE, tile_width, tile_height);
+    let image = value.get("image").and_then(|v| v.as_str()).expect("only tilesets based on a single image are supported");
+    let px_map = load_px_map(&relative_to(base_file, image));
+    let margin = value.get("margin").and_then(|v| v.as_usize()).unwrap_or(0);
+    let spacing = value.get("spacing").and_then(|v| v.as_usize()).unwrap_or(0);
+    let columns = value.get("columns").and_then(|v| v.as_usize()).unwrap_or((px_map[0].len() - margin*2 + spacing)/(TILESIZE + spacing));
+    let tile_count = value.get("tilecount").and_then(|v| v.as_usize()).unwrap_or(columns*((px_map.len() - margin*2 + spacing)/(TILESIZE + spacing)));
+    TilesetImage { firstgid, px_map, columns, tile_count, margin, spacing }
+}
+
+fn load_external_tileset(source : &str, firstgid : u32, base_file : &Path) -> TilesetImage
+{
+    let path = relative_to(base_file, source);
+    let text = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("failed to read tileset {}: {}", path.display(), e));
+    if source.ends_with(".tsj") || source.ends_with(".json")
+    {
+        tileset_from_json(&json::parse(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)), firstgid, &path)
+    }
+    else
+    {
+        tileset_from_xml(&xml::parse(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)), firstgid, &path)
+    }
+}
+
+fn load_tmx(path : &Path, text : &str) -> TiledMap
+{
+    let root = xml::parse(text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    assert!(root.name == "map", "{} is not a tiled map", path.display());
+    assert!(root.attr("infinite") != Some("1"), "infinite tiled maps aren't supported");
+    let width = attr_usize(&root, "width", path).expect("the map has no width");
+    let height = attr_usize(&root, "height", path).expect("the map has no height");
+    let mut tilesets = Vec::new();
+    for tileset in root.children_named("tileset")
+    {
+        let firstgid = attr_usize(tileset, "firstgid", path).expect("a tileset has no firstgid") as u32;
+        tilesets.push(match tileset.attr("source")
+        {
+            Some(source) => load_external_tileset(source, firstgid, path),
+            None => tileset_from_xml(tileset, firstgid, path)
+        });
+    }
+    let mut layers = Vec::new();
+    tmx_layers(&root, &mut layers);
+    TiledMap { width, height, layers, tilesets }
+}
+
+// the tile layers in order, including the ones in groups; object and image layers are left out
+fn tmx_layers(parent : &xml::Element, layers : &mut Vec<TiledLayer>)
+{
+    for layer in parent.children.iter()
+    {
+        match layer.name.as_str()
+        {
+            "layer" => {}
+            "group" =>
+            {
+                tmx_layers(layer, layers);
+                continue;
+            }
+            _ => continue
+        }
+        let data = layer.child("data").expect("a layer has no data");
+        let gids = match data.attr("encoding")
+        {
+            Some("csv") => gids_from_csv(&data.text),
+            Some("base64") => gids_from_base64(&data.text, data.attr("compression")),
+            Some(other) => panic!("unknown layer encoding {}", other),
+            None => data.children_named("tile").map(|t| t.attr("gid").map(parse_gid).unwrap_or(0)).collect()
+        };
+        layers.push(TiledLayer { name : layer.attr("name").unwrap_or("").to_string(), gids });
+    }
+}
+
+fn load_tmj(path : &Path, text : &str) -> TiledMap
+{
+    let root = json::parse(text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+    assert!(root.get("infinite") != Some(&json::Value::Bool(true)), "infinite tiled maps aren't supported");
+    let width = root.get("width").and_then(|v| v.as_usize()).expect("the map has no width");
+    let height = root.get("height").and_then(|v| v.as_usize()).expect("the map has no height");
+    let mut tilesets = Vec::new();
+    for tileset in root.get("tilesets").and_then(|v| v.as_array()).unwrap_or(&[])
+    {
+        let firstgid = tileset.get("firstgid").and_then(|v| v.as_u64()).expect("a tileset has no firstgid") as u32;
+        tilesets.push(match tileset.get("source").and_then(|v| v.as_str())
+        {
+            Some(source) => load_external_tileset(source, firstgid, path),
+            None => tileset_from_json(tileset, firstgid, path)
+        });
+    }
+    let mut layers = Vec::new();
+    tmj_layers(&root, &mut layers);
+    TiledMap { width, height, layers, tilesets }
+}
+
+// the tile layers in order, including the ones in groups; object and image layers are left out
+fn tmj_layers(parent : &json::Value, layers : &mut Vec<TiledLayer>)
+{
+    for layer in parent.get("layers").and_then(|v| v.as_array()).unwrap_or(&[])
+    {
+        match layer.get("type").and_then(|v| v.as_str())
+        {
+            Some("tilelayer") => {}
+            Some("group") =>
+            {
+                tmj_layers(layer, layers);
+                continue;
+            }
+            _ => continue
+        }
+        let data = layer.get("data").expect("a layer has no data");
+        let gids = match data
+        {
+            json::Value::Array(items) => items.iter().map(|v| v.as_u64().filter(|gid| *gid <= u32::MAX as u64).unwrap_or_else(|| panic!("bad gid {:?} in layer data", v)) as u32).collect(),
+            json::Value::String(text) => gids_from_base64(text, layer.get("compression").and_then(|v| v.as_str())),
+            _ => panic!("unknown layer data format")
+        };
+        layers.push(TiledLayer { name : layer.get("name").and_then(|v| v.as_str()).unwrap_or("").to_string(), gids });
+    }
+}
+
+pub fn is_tiled_map(path : &str) -> bool
+{
+    path.ends_with(".tmx") || path.ends_with(".tmj")
+}
+
+pub fn load_map(path : &str) -> TiledMap
+{
+    let text = std::fs::read_to_string(path).unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+    let map = if path.ends_with(".tmj") { load_tmj(Path::new(path), &text) } else { load_tmx(Path::new(path), &text) };
+    for layer in &map.layers
+    {
+        assert!(layer.gids.len() == map.width*map.height, "layer {} has {} cells, expected {}", layer.name, layer.gids.len(), map.width*map.height);
+    }
+    assert!(!map.layers.is_empty(), "{} has no tile layers", path);
+    map
+}
+
+impl TiledMap
+{
+    // pixels of a tile as it appears in the map, i.e. with its flips applied; gid 0 is a transparent tile
+    pub fn tile_pixels(&self, gid : u32) -> Vec<Rgba>
+    {
+        let mut ret = vec!(Rgba::new(0, 0, 0, 0); TILESIZE*TILESIZE);
+        let index = gid & !FLAG_MASK;
+        if index == 0
+        {
+            return ret;
+        }
+        let tileset = self.tilesets.iter().filter(|t| t.firstgid <= index).max_by_key(|t| t.firstgid).unwrap_or_else(|| panic!("no tileset for gid {}", index));
+        let local = (index - tileset.firstgid) as usize;
+        assert!(local < tileset.tile_count, "gid {} is out of range of its tileset", index);
+        let origin_x = tileset.margin + (local%tileset.columns)*(TILESIZE + tileset.spacing);
+        let origin_y = tileset.margin + (local/tileset.columns)*(TILESIZE + tileset.spacing);
+        for y in 0..TILESIZE
+        {
+            for x in 0..TILESIZE
+            {
+                // tiled applies the diagonal flip first, then horizontal, then vertical; undo them in reverse
+                let (mut sx, mut sy) = (x, y);
+                if gid & FLIPPED_VERTICALLY != 0
+                {
+                    sy = TILESIZE - 1 - sy;
+                }
+                if gid & FLIPPED_HORIZONTALLY != 0
+                {
+                    sx = TILESIZE - 1 - sx;
+                }
+                if gid & FLIPPED_DIAGONALLY != 0
+                {
+                    std::mem::swap(&mut sx, &mut sy);
+                }
+                ret[y*TILESIZE + x] = tileset.px_map[origin_y + sy][origin_x + sx];
+            }
+        }
+        ret
+    }
+    // one TileId per distinct gid (flip flags included, so each orientation is its own tile), in order of first appearance
+    pub fn to_example(&self, layer : usize) -> (Vec<Vec<TileId>>, Vec<Vec<Rgba>>)
+    {
+        let mut gid_to_id = BTreeMap::new();
+        let mut id_to_tile = Vec::new();
+        let mut map = Vec::with_capacity(self.height);
+        for y in 0..self.height
+        {
+            let mut row = Vec::with_capacity(self.width);
+            for x in 0..self.width
+            {
+                let gid = self.layers[layer].gids[y*self.width + x];
+                let id = *gid_to_id.entry(gid).or_insert_with(|| { id_to_tile.push(self.tile_pixels(gid)); id_to_tile.len() - 1 });
+                row.push(id);
+            }
+            map.push(row);
+        }
+        (map, id_to_tile)
+    }
+}
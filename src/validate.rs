@@ -0,0 +1,167 @@
+use crate::{export, get_opposite_direction, Collapser, TileId, TileType, DIRECTIONS, TILESIZE};
+use std::fmt::Write;
+
+// two neighboring cells whose tiles were never seen next to each other that way in the example
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Violation
+{
+    pub cell : (usize, usize),
+    pub neighbor : (usize, usize),
+    pub tile : TileId,
+    pub neighbor_tile : TileId,
+}
+
+// everything wrong with a finished (or hand-edited) map
+#[derive(Clone)]
+#[derive(Debug)]
+#[derive(Default)]
+pub struct Report
+{
+    pub width : usize,
+    pub height : usize,
+    pub violations : Vec<Violation>,
+    pub dead : Vec<(usize, usize)>,
+    // forbidden tiles (the example's border tile) anywhere but the output's border ring
+    pub forbidden : Vec<((usize, usize), TileId)>,
+    // tile ids the example doesn't have, which can only come from editing a map by hand
+    pub unknown : Vec<((usize, usize), TileId)>,
+}
+
+const VIOLATION_COLOR : image::Rgba<u8> = image::Rgba([255, 0, 0, 255]);
+const DEAD_COLOR : image::Rgba<u8> = image::Rgba([255, 0, 255, 255]);
+const FORBIDDEN_COLOR : image::Rgba<u8> = image::Rgba([255, 160, 0, 255]);
+const UNKNOWN_COLOR : image::Rgba<u8> = image::Rgba([0, 255, 255, 255]);
+
+fn outline(image : &mut image::RgbaImage, (x, y) : (usize, usize), color : image::Rgba<u8>)
+{
+    for i in 0..TILESIZE
+    {
+        for (tx, ty) in [(i, 0), (i, TILESIZE - 1), (0, i), (TILESIZE - 1, i)]
+        {
+            image.put_pixel((x*TILESIZE + tx) as u32, (y*TILESIZE + ty) as u32, color);
+        }
+    }
+}
+
+fn cells_json(cells : &[(usize, usize)]) -> String
+{
+    cells.iter().map(|(x, y)| format!("[{}, {}]", x, y)).collect::<Vec<_>>().join(", ")
+}
+
+impl Report
+{
+    pub fn is_valid(&self) -> bool
+    {
+        self.violations.is_empty() && self.dead.is_empty() && self.forbidden.is_empty() && self.unknown.is_empty()
+    }
+    pub fn print_summary(&self)
+    {
+        println!("validation: {} adjacency violations, {} dead cells, {} forbidden tiles, {} unknown tiles",
+            self.violations.len(), self.dead.len(), self.forbidden.len(), self.unknown.len());
+        for violation in self.violations.iter()
+        {
+            println!("!!!!---- tile {} at {},{} can't be next to tile {} at {},{}", violation.tile, violation.cell.0, violation.cell.1,
+                violation.neighbor_tile, violation.neighbor.0, violation.neighbor.1);
+        }
+    }
+    pub fn to_json(&self) -> String
+    {
+        let mut out = String::new();
+        writeln!(out, "{{").unwrap();
+        writeln!(out, "  \"width\": {},", self.width).unwrap();
+        writeln!(out, "  \"height\": {},", self.height).unwrap();
+        writeln!(out, "  \"valid\": {},", self.is_valid()).unwrap();
+        writeln!(out, "  \"violation_count\": {},", self.violations.len()).unwrap();
+        writeln!(out, "  \"dead_count\": {},", self.dead.len()).unwrap();
+        writeln!(out, "  \"forbidden_count\": {},", self.forbidden.len()).unwrap();
+        writeln!(out, "  \"unknown_count\": {},", self.unknown.len()).unwrap();
+        let violations = self.violations.iter().map(|v|
+            format!("{{\"cell\": [{}, {}], \"tile\": {}, \"neighbor\": [{}, {}], \"neighbor_tile\": {}}}",
+                v.cell.0, v.cell.1, v.tile, v.neighbor.0, v.neighbor.1, v.neighbor_tile)).collect::<Vec<_>>();
+        writeln!(out, "  \"violations\": [{}],", violations.join(", ")).unwrap();
+        writeln!(out, "  \"dead\": [{}],", cells_json(&self.dead)).unwrap();
+        let forbidden = self.forbidden.iter().map(|((x, y), id)| format!("{{\"cell\": [{}, {}], \"tile\": {}}}", x, y, id)).collect::<Vec<_>>();
+        writeln!(out, "  \"forbidden\": [{}],", forbidden.join(", ")).unwrap();
+        let unknown = self.unknown.iter().map(|((x, y), id)| format!("{{\"cell\": [{}, {}], \"tile\": {}}}", x, y, id)).collect::<Vec<_>>();
+        writeln!(out, "  \"unknown\": [{}]", unknown.join(", ")).unwrap();
+        writeln!(out, "}}").unwrap();
+        out
+    }
+}
+
+impl Collapser
+{
+    // checks a finished map against what was learned from the example. every pair of neighbors (not wrapping around)
+    // has to have been seen that way around in both directions; the map doesn't have to be one this collapser made
+    pub fn validate(&self, types : &[TileType], width : usize, height : usize) -> Report
+    {
+        assert!(types.len() == width*height, "a {}x{} map needs {} cells, got {}", width, height, width*height, types.len());
+        let mut report = Report { width, height, ..Default::default() };
+        let known = |tile_type : TileType| match tile_type
+        {
+            TileType::Tile(id) if id < self.max_index => Some(id),
+            _ => None
+        };
+        for y in 0..height
+        {
+            for x in 0..width
+            {
+                let on_ring = x == 0 || y == 0 || x == width - 1 || y == height - 1;
+                match types[y*width + x]
+                {
+                    TileType::Tile(id) if id >= self.max_index => report.unknown.push(((x, y), id)),
+                    TileType::Tile(id) if !on_ring && self.forbidden_tiles.contains(&id) => report.forbidden.push(((x, y), id)),
+                    TileType::Tile(_) => {}
+                    _ => report.dead.push((x, y)),
+                }
+                let Some(a) = known(types[y*width + x]) else { continue };
+                for (dir, offset) in DIRECTIONS.iter().enumerate()
+                {
+                    let (nx, ny) = (x as isize + offset.0, y as isize + offset.1);
+                    // each pair once, from whichever of the two comes first
+                    if nx < 0 || ny < 0 || nx as usize >= width || ny as usize >= height || (ny as usize)*width + (nx as usize) < y*width + x
+                    {
+                        continue;
+                    }
+                    let neighbor = (nx as usize, ny as usize);
+                    let Some(b) = known(types[neighbor.1*width + neighbor.0]) else { continue };
+                    if self.edge_weight(a, b, dir) == 0.0 || self.edge_weight(b, a, get_opposite_direction(dir)) == 0.0
+                    {
+                        report.violations.push(Violation { cell : (x, y), neighbor, tile : a, neighbor_tile : b });
+                    }
+                }
+            }
+        }
+        report
+    }
+    // the map with everything in the report outlined: violations red, dead cells magenta, forbidden tiles orange and
+    // unknown ones cyan
+    pub fn render_report(&self, report : &Report, types : &[TileType]) -> image::RgbaImage
+    {
+        let drawable = types.iter().map(|t| match t
+        {
+            TileType::Tile(id) if *id >= self.max_index => TileType::Dead,
+            t => *t
+        }).collect::<Vec<_>>();
+        let mut image = export::render_grid(&drawable, report.width, report.height, &self.id_to_tile);
+        for violation in report.violations.iter()
+        {
+            outline(&mut image, violation.cell, VIOLATION_COLOR);
+            outline(&mut image, violation.neighbor, VIOLATION_COLOR);
+        }
+        report.dead.iter().for_each(|cell| outline(&mut image, *cell, DEAD_COLOR));
+        report.forbidden.iter().for_each(|(cell, _)| outline(&mut image, *cell, FORBIDDEN_COLOR));
+        report.unknown.iter().for_each(|(cell, _)| outline(&mut image, *cell, UNKNOWN_COLOR));
+        image
+    }
+    // {stem}_report.json and {stem}_report.png
+    pub fn write_report(&self, stem : &str, types : &[TileType], width : usize, height : usize) -> Report
+    {
+        let report = self.validate(types, width, height);
+        report.print_summary();
+        std::fs::write(format!("{}_report.json", stem), report.to_json()).unwrap();
+        self.render_report(&report, types).save(format!("{}_report.png", stem)).unwrap();
+        report
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{output_stem, Collapser, Rgba, TileId, TileType};
+use std::collections::HashMap;
+
+// an endless map made a chunk at a time, in whatever order chunks are asked for. each chunk is an output of its own
+// whose border ring, instead of the border tile, is the edge of the neighboring chunks that already exist, so the
+// chunk has to fit against them; sides with no neighbor yet are left dead, which doesn't constrain anything, and the
+// neighbor made there later has to fit this chunk in turn. each chunk's seed comes from the world seed and the
+// chunk's coordinates, so the same chunks asked for in the same order always come out the same. a chunk's content
+// still depends on which of its neighbors were made before it, so asking for chunks in another order (or making a
+// chunk alone) gives a different world
+pub struct World
+{
+    // set up (settings, constraints, size) but not run; every chunk is a fork of it
+    template : Collapser,
+    seed : u128,
+    // without the ring
+    chunk_width : usize,
+    chunk_height : usize,
+    // the chunks made so far, row-major without the ring
+    chunks : HashMap<(isize, isize), Vec<TileType>>,
+}
+
+impl World
+{
+    // chunks are the template's output size minus its border ring
+    pub fn new(template : Collapser, seed : u128) -> World
+    {
+        let (chunk_width, chunk_height) = (template.width - 2, template.height - 2);
+        World { template, seed, chunk_width, chunk_height, chunks : HashMap::new() }
+    }
+    // mixed so that neighboring chunks don't get neighboring seeds
+    pub fn chunk_seed(&self, (cx, cy) : (isize, isize)) -> u128
+    {
+        let mut rng = oorandom::Rand64::new(self.seed ^ ((cx as u64 as u128) << 64 | cy as u64 as u128));
+        (rng.rand_u64() as u128) << 64 | rng.rand_u64() as u128
+    }
+    pub fn chunk(&self, coord : (isize, isize)) -> Option<&[TileType]>
+    {
+        self.chunks.get(&coord).map(|chunk| chunk.as_slice())
+    }
+    // a cell of the world in world coordinates; dead where no chunk has been made yet
+    pub fn get_type(&self, x : isize, y : isize) -> TileType
+    {
+        let (w, h) = (self.chunk_width as isize, self.chunk_height as isize);
+        match self.chunk((x.div_euclid(w), y.div_euclid(h)))
+        {
+            Some(chunk) => chunk[(y.rem_euclid(h)*w + x.rem_euclid(w)) as usize],
+            None => TileType::Dead,
+        }
+    }
+    // makes the chunk if it hasn't been made yet, and returns it
+    pub fn generate(&mut self, coord : (isize, isize)) -> &[TileType]
+    {
+        if !self.chunks.contains_key(&coord)
+        {
+            let (w, h) = (self.chunk_width as isize, self.chunk_height as isize);
+            let (left, top) = (coord.0*w, coord.1*h);
+            let namebase = format!("{}_{}_{}.png", output_stem(&self.template.namebase), coord.0, coord.1);
+            let mut collapser = self.template.fork(namebase);
+            collapser.pin_ring(|x, y| self.get_type(left + x - 1, top + y - 1));
+            let seed = self.chunk_seed(coord);
+            println!("chunk {},{} seed: {}", coord.0, coord.1, seed);
+            collapser.run(seed);
+            let mut chunk = Vec::with_capacity(self.chunk_width*self.chunk_height);
+            for y in 1..=h
+            {
+                for x in 1..=w
+                {
+                    chunk.push(collapser.get_type(x, y));
+                }
+            }
+            self.chunks.insert(coord, chunk);
+        }
+        &self.chunks[&coord]
+    }
+    // the cells of a rectangle of chunks, cols by rows of them from the top left one, as one row-major grid
+    pub fn stitch(&self, (cx, cy) : (isize, isize), (cols, rows) : (usize, usize)) -> (Vec<TileType>, usize, usize)
+    {
+        let (width, height) = (cols*self.chunk_width, rows*self.chunk_height);
+        let (left, top) = (cx*self.chunk_width as isize, cy*self.chunk_height as isize);
+        let mut types = Vec::with_capacity(width*height);
+        for y in 0..height as isize
+        {
+            for x in 0..width as isize
+            {
+                types.push(self.get_type(left + x, top + y));
+            }
+        }
+        (types, width, height)
+    }
+    pub fn id_to_tile(&self) -> &[Vec<Rgba>]
+    {
+        &self.template.id_to_tile
+    }
+    pub fn tile_count(&self) -> TileId
+    {
+        self.template.max_index
+    }
+}
+
+impl Collapser
+{
+    // replaces the border ring init_edges put in with the given cells, for an output that's set up but not run yet.
+    // dead ones leave that side open
+    pub(crate) fn pin_ring(&mut self, ring : impl Fn(isize, isize) -> TileType)
+    {
+        let (w, h) = (self.width as isize, self.height as isize);
+        for y in 0..h
+        {
+            for x in 0..w
+            {
+                if x != 0 && y != 0 && x != w-1 && y != h-1
+                {
+                    continue;
+                }
+                let tile_type = ring(x, y);
+                assert!(tile_type != TileType::Field, "{},{} is on the ring and has to be decided", x, y);
+                *self.get_type_mut(x, y) = tile_type;
+                // the fields solver only picks cells next to decided ones, and there might not be any
+                let inside = (x.clamp(1, w-2), y.clamp(1, h-2));
+                if tile_type == TileType::Dead && (inside.0 - x).abs() + (inside.1 - y).abs() == 1
+                {
+                    self.candidates.push(inside);
+                }
+            }
+        }
+        self.finish_pinning();
+    }
+}
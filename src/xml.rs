@@ -0,0 +1,208 @@
+// just enough xml to read tiled's .tmx/.tsx files: elements, attributes and text, no namespaces or dtds. hand-written
+// for the same reason as json.rs; those files don't use anything more. anything malformed is an Err, never a panic
+
+#[derive(Clone)]
+#[derive(Debug)]
+pub struct Element
+{
+    pub name : String,
+    pub attributes : Vec<(String, String)>,
+    pub children : Vec<Element>,
+    pub text : String,
+}
+
+impl Element
+{
+    pub fn attr(&self, key : &str) -> Option<&str>
+    {
+        self.attributes.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+    // None if it isn't there, an Err if it isn't a number
+    pub fn attr_usize(&self, key : &str) -> Result<Option<usize>, String>
+    {
+        self.attr(key).map(|v| v.trim().parse().map_err(|_| format!("attribute {} of <{}> is not a number: {}", key, self.name, v))).transpose()
+    }
+    pub fn child(&self, name : &str) -> Option<&Element>
+    {
+        self.children.iter().find(|c| c.name == name)
+    }
+    pub fn children_named<'a>(&'a self, name : &'a str) -> impl Iterator<Item = &'a Element>
+    {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+fn unescape(s : &str) -> String
+{
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+// deeper than this is an error instead of a stack overflow
+const MAX_DEPTH : usize = 256;
+
+struct Parser<'a>
+{
+    text : &'a str,
+    pos : usize,
+    depth : usize,
+}
+
+impl<'a> Parser<'a>
+{
+    fn error(&self, what : &str) -> String
+    {
+        format!("xml error at byte {}: {}", self.pos, what)
+    }
+    fn rest(&self) -> &'a str
+    {
+        &self.text[self.pos..]
+    }
+    fn skip_whitespace(&mut self)
+    {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+    fn skip_past(&mut self, end : &str) -> Result<(), String>
+    {
+        let found = self.rest().find(end).ok_or_else(|| self.error(&format!("missing {}", end)))?;
+        self.pos += found + end.len();
+        Ok(())
+    }
+    // skips the prolog, comments and processing instructions
+    fn skip_misc(&mut self) -> Result<(), String>
+    {
+        loop
+        {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?")
+            {
+                self.skip_past("?>")?;
+            }
+            else if self.rest().starts_with("<!--")
+            {
+                self.skip_past("-->")?;
+            }
+            else if self.rest().starts_with("<!")
+            {
+                self.skip_past(">")?;
+            }
+            else
+            {
+                return Ok(());
+            }
+        }
+    }
+    fn name(&mut self) -> Result<String, String>
+    {
+        let rest = self.rest();
+        let len = rest.find(|c : char| c.is_whitespace() || "<>/='\"".contains(c)).unwrap_or(rest.len());
+        if len == 0
+        {
+            return Err(self.error("expected a name"));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_string())
+    }
+    fn element(&mut self) -> Result<Element, String>
+    {
+        self.skip_misc()?;
+        if !self.rest().starts_with('<')
+        {
+            return Err(self.error("expected an element"));
+        }
+        if self.depth >= MAX_DEPTH
+        {
+            return Err(self.error("nested too deeply"));
+        }
+        self.pos += 1;
+        let name = self.name()?;
+        let mut element = Element { name, attributes : Vec::new(), children : Vec::new(), text : String::new() };
+        loop
+        {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>")
+            {
+                self.pos += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>')
+            {
+                self.pos += 1;
+                break;
+            }
+            let key = self.name()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with('=')
+            {
+                return Err(self.error("expected '=' after attribute name"));
+            }
+            self.pos += 1;
+            self.skip_whitespace();
+            let quote = self.rest().chars().next().ok_or_else(|| self.error("unexpected end of input"))?;
+            if quote != '"' && quote != '\''
+            {
+                return Err(self.error("expected a quoted attribute value"));
+            }
+            self.pos += 1;
+            let len = self.rest().find(quote).ok_or_else(|| self.error("unterminated attribute value"))?;
+            element.attributes.push((key, unescape(&self.rest()[..len])));
+            self.pos += len + 1;
+        }
+        loop
+        {
+            let len = self.rest().find('<').ok_or_else(|| self.error(&format!("unterminated <{}>", element.name)))?;
+            element.text.push_str(&unescape(&self.rest()[..len]));
+            self.pos += len;
+            if self.rest().starts_with("</")
+            {
+                self.pos += 2;
+                if self.name()? != element.name
+                {
+                    return Err(self.error(&format!("expected </{}>", element.name)));
+                }
+                self.skip_whitespace();
+                if !self.rest().starts_with('>')
+                {
+                    return Err(self.error("expected '>'"));
+                }
+                self.pos += 1;
+                return Ok(element);
+            }
+            else if self.rest().starts_with("<!--")
+            {
+                self.skip_past("-->")?;
+            }
+            else if self.rest().starts_with("<![CDATA[")
+            {
+                self.pos += "<![CDATA[".len();
+                let len = self.rest().find("]]>").ok_or_else(|| self.error("unterminated CDATA"))?;
+                element.text.push_str(&self.rest()[..len]);
+                self.pos += len + 3;
+            }
+            else
+            {
+                self.depth += 1;
+                let child = self.element();
+                self.depth -= 1;
+                element.children.push(child?);
+            }
+        }
+    }
+}
+
+pub fn parse(text : &str) -> Result<Element, String>
+{
+    let mut parser = Parser { text, pos : 0, depth : 0 };
+    let root = parser.element()?;
+    parser.skip_misc()?;
+    if !parser.rest().is_empty()
+    {
+        return Err(parser.error("trailing characters"));
+    }
+    Ok(root)
+}
+
+pub fn escape(s : &str) -> String
+{
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}